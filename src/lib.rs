@@ -272,7 +272,11 @@ use self::LabelText::*;
 use std::borrow::Cow;
 use std::io::prelude::*;
 use std::io;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
 
 /// The text for a graphviz label on a node or edge.
 pub enum LabelText<'a> {
@@ -306,6 +310,7 @@ pub enum LabelText<'a> {
 /// See https://graphviz.org/doc/info/attrs.html#k:style for descriptions.
 /// Note that some of these are not valid for edges.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Style {
     None,
     Solid,
@@ -317,6 +322,15 @@ pub enum Style {
     Filled,
     Striped,
     Wedged,
+    /// An edge that narrows from its tail to its head. Its width at the
+    /// tail is the `penwidth`, and it is usually drawn without an
+    /// arrowhead (`Arrow::none`); `dir` selects the direction of the
+    /// taper.
+    Tapered,
+    /// A radial gradient fill, from the center outwards, between the
+    /// colors of a `"color1:color2"` fill color. See also
+    /// `Labeller::node_gradientangle`.
+    Radial,
 }
 
 impl Style {
@@ -332,6 +346,31 @@ impl Style {
             Style::Filled => "filled",
             Style::Striped => "striped",
             Style::Wedged => "wedged",
+            Style::Tapered => "tapered",
+            Style::Radial => "radial",
+        }
+    }
+}
+
+impl FromStr for Style {
+    type Err = ParseError;
+
+    /// Parses the strings produced by `as_slice`, ignoring case.
+    fn from_str(s: &str) -> Result<Style, ParseError> {
+        match &s.to_ascii_lowercase()[..] {
+            "" => Ok(Style::None),
+            "solid" => Ok(Style::Solid),
+            "dashed" => Ok(Style::Dashed),
+            "dotted" => Ok(Style::Dotted),
+            "bold" => Ok(Style::Bold),
+            "rounded" => Ok(Style::Rounded),
+            "diagonals" => Ok(Style::Diagonals),
+            "filled" => Ok(Style::Filled),
+            "striped" => Ok(Style::Striped),
+            "wedged" => Ok(Style::Wedged),
+            "tapered" => Ok(Style::Tapered),
+            "radial" => Ok(Style::Radial),
+            _ => Err(ParseError::new(s)),
         }
     }
 }
@@ -340,6 +379,7 @@ impl Style {
 /// The direction to draw directed graphs (one rank at a time)
 /// See https://graphviz.org/docs/attr-types/rankdir/ for descriptions
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RankDir {
     TopBottom,
     LeftRight,
@@ -358,6 +398,437 @@ impl RankDir {
     }
 }
 
+impl FromStr for RankDir {
+    type Err = ParseError;
+
+    /// Parses the strings produced by `as_slice`, ignoring case.
+    fn from_str(s: &str) -> Result<RankDir, ParseError> {
+        match &s.to_ascii_uppercase()[..] {
+            "TB" => Ok(RankDir::TopBottom),
+            "LR" => Ok(RankDir::LeftRight),
+            "BT" => Ok(RankDir::BottomTop),
+            "RL" => Ok(RankDir::RightLeft),
+            _ => Err(ParseError::new(s)),
+        }
+    }
+}
+
+/// The error returned when parsing a string that does not name any
+/// variant of `Style` or `RankDir`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    value: String,
+}
+
+impl ParseError {
+    fn new(value: &str) -> ParseError {
+        ParseError { value: value.to_string() }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown value `{}`", self.value)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The error returned by `validate`, with the line and column, both
+/// counted from 1, at which the problem was found.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ValidationError {
+    line: usize,
+    column: usize,
+    message: &'static str,
+}
+
+impl ValidationError {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn message(&self) -> &str {
+        self.message
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// The common graphviz node shapes.
+/// See https://graphviz.org/doc/info/shapes.html for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Shape {
+    Box,
+    Circle,
+    DoubleCircle,
+    Ellipse,
+    Oval,
+    Diamond,
+    Triangle,
+    Hexagon,
+    Octagon,
+    Square,
+    Record,
+    Mrecord,
+    Plaintext,
+    Plain,
+    Point,
+    Note,
+    Cylinder,
+    None_,
+}
+
+impl Shape {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            Shape::Box => "box",
+            Shape::Circle => "circle",
+            Shape::DoubleCircle => "doublecircle",
+            Shape::Ellipse => "ellipse",
+            Shape::Oval => "oval",
+            Shape::Diamond => "diamond",
+            Shape::Triangle => "triangle",
+            Shape::Hexagon => "hexagon",
+            Shape::Octagon => "octagon",
+            Shape::Square => "square",
+            Shape::Record => "record",
+            Shape::Mrecord => "Mrecord",
+            Shape::Plaintext => "plaintext",
+            Shape::Plain => "plain",
+            Shape::Point => "point",
+            Shape::Note => "note",
+            Shape::Cylinder => "cylinder",
+            Shape::None_ => "none",
+        }
+    }
+}
+
+/// How edges are drawn by the layout engine.
+/// See https://graphviz.org/docs/attrs/splines/ for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Splines {
+    None_,
+    Line,
+    Polyline,
+    Curved,
+    Ortho,
+    Spline,
+}
+
+impl Splines {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            Splines::None_ => "none",
+            Splines::Line => "line",
+            Splines::Polyline => "polyline",
+            Splines::Curved => "curved",
+            Splines::Ortho => "ortho",
+            Splines::Spline => "spline",
+        }
+    }
+}
+
+/// The order in which the edges of a node are laid out.
+/// See https://graphviz.org/docs/attrs/ordering/ for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Ordering {
+    Out,
+    In,
+}
+
+impl Ordering {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            Ordering::Out => "out",
+            Ordering::In => "in",
+        }
+    }
+}
+
+/// Whether, and with what margin in points, the disconnected
+/// components of a graph are packed together.
+/// See https://graphviz.org/docs/attrs/pack/ for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pack {
+    Enabled,
+    Margin(u32),
+    Disabled,
+}
+
+impl Pack {
+    pub fn to_dot_string(self) -> String {
+        match self {
+            Pack::Enabled => "true".to_string(),
+            Pack::Margin(margin) => margin.to_string(),
+            Pack::Disabled => "false".to_string(),
+        }
+    }
+}
+
+/// How the disconnected components of a graph are packed.
+/// See https://graphviz.org/docs/attr-types/packMode/ for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PackMode {
+    Node,
+    Cluster,
+    Graph,
+    Array,
+}
+
+impl PackMode {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            PackMode::Node => "node",
+            PackMode::Cluster => "clust",
+            PackMode::Graph => "graph",
+            PackMode::Array => "array",
+        }
+    }
+}
+
+/// How the drawing is scaled to the aspect ratio given by the graph
+/// `size`, or a numeric aspect ratio to apply.
+/// See https://graphviz.org/docs/attrs/ratio/ for descriptions
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Ratio {
+    Fill,
+    Compress,
+    Expand,
+    Auto,
+    Numeric(f64),
+}
+
+impl Ratio {
+    pub fn to_dot_string(self) -> String {
+        match self {
+            Ratio::Fill => "fill".to_string(),
+            Ratio::Compress => "compress".to_string(),
+            Ratio::Expand => "expand".to_string(),
+            Ratio::Auto => "auto".to_string(),
+            Ratio::Numeric(ratio) => ratio.to_string(),
+        }
+    }
+
+    fn is_finite(self) -> bool {
+        match self {
+            Ratio::Numeric(ratio) => ratio.is_finite(),
+            _ => true,
+        }
+    }
+}
+
+/// The margin kept around nodes or edges when removing overlaps,
+/// either added to their size, in points, or as a factor scaling it.
+/// Negative values are clamped to zero.
+/// See https://graphviz.org/docs/attrs/sep/ for descriptions
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Sep {
+    Additive(f64),
+    Scale(f64),
+}
+
+impl Sep {
+    pub fn to_dot_string(self) -> String {
+        match self {
+            Sep::Additive(margin) => format!("+{}", non_negative(margin)),
+            Sep::Scale(factor) => non_negative(factor).to_string(),
+        }
+    }
+
+    fn is_finite(self) -> bool {
+        match self {
+            Sep::Additive(x) | Sep::Scale(x) => x.is_finite(),
+        }
+    }
+}
+
+fn non_negative(x: f64) -> f64 {
+    if x > 0.0 { x } else { 0.0 }
+}
+
+/// How the force-directed layouts remove overlapping nodes, if at all.
+/// See https://graphviz.org/docs/attrs/overlap/ for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Overlap {
+    True,
+    False,
+    Scale,
+    Prism,
+    Compress,
+    Vpsc,
+}
+
+impl Overlap {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            Overlap::True => "true",
+            Overlap::False => "false",
+            Overlap::Scale => "scale",
+            Overlap::Prism => "prism",
+            Overlap::Compress => "compress",
+            Overlap::Vpsc => "vpsc",
+        }
+    }
+}
+
+/// The vertical placement of the graph label.
+/// See https://graphviz.org/docs/attrs/labelloc/ for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LabelLoc {
+    Top,
+    Center,
+    Bottom,
+}
+
+impl LabelLoc {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            LabelLoc::Top => "t",
+            LabelLoc::Center => "c",
+            LabelLoc::Bottom => "b",
+        }
+    }
+}
+
+/// The horizontal placement of the graph label.
+/// See https://graphviz.org/docs/attrs/labeljust/ for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LabelJust {
+    Left,
+    Center,
+    Right,
+}
+
+impl LabelJust {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            LabelJust::Left => "l",
+            LabelJust::Center => "c",
+            LabelJust::Right => "r",
+        }
+    }
+}
+
+/// The position of a node image within the node.
+/// See https://graphviz.org/docs/attrs/imagepos/ for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImagePos {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    MiddleLeft,
+    MiddleCenter,
+    MiddleRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl ImagePos {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            ImagePos::TopLeft => "tl",
+            ImagePos::TopCenter => "tc",
+            ImagePos::TopRight => "tr",
+            ImagePos::MiddleLeft => "ml",
+            ImagePos::MiddleCenter => "mc",
+            ImagePos::MiddleRight => "mr",
+            ImagePos::BottomLeft => "bl",
+            ImagePos::BottomCenter => "bc",
+            ImagePos::BottomRight => "br",
+        }
+    }
+}
+
+/// The dimensions of a node, in inches. Unset dimensions are left to
+/// the layout engine; if `fixed` is set the node is drawn at exactly
+/// the given size regardless of its label.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeSize {
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+    pub fixed: bool,
+}
+
+impl NodeSize {
+    /// Renders the set dimensions as a sequence of attributes.
+    fn to_dot_string(self) -> String {
+        let mut res = String::new();
+        if let Some(width) = self.width.filter(|w| w.is_finite()) {
+            res.push_str(&format!("[width={}]", width));
+        }
+        if let Some(height) = self.height.filter(|h| h.is_finite()) {
+            res.push_str(&format!("[height={}]", height));
+        }
+        if self.fixed {
+            res.push_str("[fixedsize=true]");
+        }
+        res
+    }
+}
+
+/// The font used for the text of a drawing. Unset fields are left to
+/// Graphviz.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Font<'a> {
+    /// The font name, e.g. `Helvetica Neue`.
+    pub name: Option<Cow<'a, str>>,
+    /// The font size, in points.
+    pub size: Option<f64>,
+}
+
+/// The line used to draw the outline of a node or an edge, grouping
+/// its `color`, `penwidth` and `style` attributes.
+pub struct Pen<'a> {
+    /// The color of the line; see `Labeller::node_color`.
+    pub color: Option<LabelText<'a>>,
+    /// The width of the line, in points.
+    pub width: Option<f64>,
+    /// The style of the line. `Style::None` leaves the style unset.
+    pub style: Style,
+}
+
+impl<'a> Pen<'a> {
+    /// Renders the set fields as a sequence of attributes, leaving out
+    /// the color or the style if they are not wanted.
+    fn to_dot_string(&self, color: bool, style: bool) -> String {
+        let mut res = String::new();
+        if let (true, Some(c)) = (color, &self.color) {
+            res.push_str(&format!("[color={}]", c.to_dot_string()));
+        }
+        if let Some(width) = self.width.filter(|w| w.is_finite()) {
+            res.push_str(&format!("[penwidth={}]", width));
+        }
+        if style && self.style != Style::None {
+            res.push_str(&format!("[style=\"{}\"]", self.style.as_slice()));
+        }
+        res
+    }
+}
+
 // There is a tension in the design of the labelling API.
 //
 // For example, I considered making a `Labeller<T>` trait that
@@ -393,15 +864,18 @@ impl RankDir {
 /// `Id` is a Graphviz `ID`.
 pub struct Id<'a> {
     name: Cow<'a, str>,
+    quoted: bool,
 }
 
 impl<'a> Id<'a> {
     /// Creates an `Id` named `name`.
     ///
     /// The caller must ensure that the input conforms to an
-    /// identifier format: it must be a non-empty string made up of
-    /// alphanumeric or underscore characters, not beginning with a
-    /// digit (i.e. the regular expression `[a-zA-Z_][a-zA-Z_0-9]*`).
+    /// identifier format: it must either be a non-empty string made up
+    /// of alphanumeric or underscore characters, not beginning with a
+    /// digit (i.e. the regular expression `[a-zA-Z_][a-zA-Z_0-9]*`),
+    /// or a numeral (i.e. the regular expression
+    /// `-?(\.[0-9]+|[0-9]+(\.[0-9]*)?)`).
     ///
     /// (Note: this format is a strict subset of the `ID` format
     /// defined by the DOT language.  This function may change in the
@@ -410,33 +884,39 @@ impl<'a> Id<'a> {
     ///
     /// Passing an invalid string (containing spaces, brackets,
     /// quotes, ...) will return an empty `Err` value.
+    #[allow(clippy::result_unit_err)]
     pub fn new<Name: Into<Cow<'a, str>>>(name: Name) -> Result<Id<'a>, ()> {
         let name = name.into();
-        {
-            let mut chars = name.chars();
-            match chars.next() {
-                Some(c) if is_letter_or_underscore(c) => {}
-                _ => return Err(()),
-            }
-            if !chars.all(is_constituent) {
-                return Err(())
-            }
+        if !is_identifier(&name) && !is_numeral(&name) {
+            return Err(())
         }
-        return Ok(Id{ name: name });
+        Ok(Id{ name, quoted: false })
+    }
 
-        fn is_letter_or_underscore(c: char) -> bool {
-            in_range('a', c, 'z') || in_range('A', c, 'Z') || c == '_'
-        }
-        fn is_constituent(c: char) -> bool {
-            is_letter_or_underscore(c) || in_range('0', c, '9')
-        }
-        fn in_range(low: char, c: char, high: char) -> bool {
-            low as usize <= c as usize && c as usize <= high as usize
-        }
+    /// Creates an `Id` named `name`, which may be any string, e.g. one
+    /// derived from untrusted input. A `name` that `Id::new` accepts,
+    /// and that is not a DOT keyword, is written as is; anything else
+    /// is escaped and wrapped in quotes when the id is written. `name`
+    /// and `as_slice` return `name` unchanged, so the default
+    /// `Labeller::node_label` shows it without quotes.
+    pub fn quoted<Name: Into<Cow<'a, str>>>(name: Name) -> Id<'a> {
+        let name = name.into();
+        let quoted = !((is_identifier(&name) && !is_keyword(&name)) || is_numeral(&name));
+        Id{ name, quoted }
     }
 
     pub fn as_slice(&'a self) -> &'a str {
-        &*self.name
+        &self.name
+    }
+
+    /// The id as it is written in DOT, escaped and quoted if it was
+    /// created by `Id::quoted` from a name that needs it.
+    pub fn to_dot_string(&self) -> Cow<'_, str> {
+        if self.quoted {
+            format!("\"{}\"", LabelText::escape_default(&self.name)).into()
+        } else {
+            Cow::Borrowed(&self.name)
+        }
     }
 
     pub fn name(self) -> Cow<'a, str> {
@@ -444,12 +924,54 @@ impl<'a> Id<'a> {
     }
 }
 
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if is_letter_or_underscore(c) => chars.all(is_constituent),
+        _ => false,
+    }
+}
+
+fn is_numeral(s: &str) -> bool {
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let (integer, fraction) = match s.find('.') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let is_digits = |s: &str| s.chars().all(|c| in_range('0', c, '9'));
+    match fraction {
+        Some(fraction) => {
+            is_digits(integer) && is_digits(fraction)
+                && !(integer.is_empty() && fraction.is_empty())
+        }
+        None => !integer.is_empty() && is_digits(integer),
+    }
+}
+
+/// The keywords of the DOT language, which are matched case-insensitively
+/// and cannot be used as bare identifiers.
+fn is_keyword(s: &str) -> bool {
+    ["node", "edge", "graph", "digraph", "subgraph", "strict"].iter().any(|k| k.eq_ignore_ascii_case(s))
+}
+
+fn is_letter_or_underscore(c: char) -> bool {
+    in_range('a', c, 'z') || in_range('A', c, 'Z') || c == '_'
+}
+
+fn is_constituent(c: char) -> bool {
+    is_letter_or_underscore(c) || in_range('0', c, '9')
+}
+
+fn in_range(low: char, c: char, high: char) -> bool {
+    low as usize <= c as usize && c as usize <= high as usize
+}
+
 /// Each instance of a type that implements `Label<C>` maps to a
 /// unique identifier with respect to `C`, which is used to identify
 /// it in the generated .dot file. They can also provide more
 /// elaborate (and non-unique) label text that is used in the graphviz
 /// rendered output.
-
+///
 /// The graph instance is responsible for providing the DOT compatible
 /// identifiers for the nodes and (optionally) rendered labels for the nodes and
 /// edges, as well as an identifier for the graph itself.
@@ -458,23 +980,55 @@ pub trait Labeller<'a,N,E> {
     fn graph_id(&'a self) -> Id<'a>;
 
     /// A list of attributes to apply to the graph
-    fn graph_attrs(&'a self) -> HashMap<&str, &str> {
+    fn graph_attrs(&'a self) -> HashMap<Cow<'a, str>, Cow<'a, str>> {
+        HashMap::default()
+    }
+
+    /// A list of attributes to apply to the subgraph `id` returned by
+    /// `node_subgraph`, such as its `label` or `bgcolor`. The values
+    /// are quoted when rendered.
+    fn subgraph_attrs(&'a self, _id: &Id) -> HashMap<Cow<'a, str>, Cow<'a, str>> {
         HashMap::default()
     }
 
+    /// A list of raw statements, such as `{rank=same; a; b}`, written
+    /// verbatim just before the closing brace of the graph. A statement
+    /// with unbalanced braces is reported as an `io::Error` of kind
+    /// `InvalidInput` when rendering.
+    fn trailing_statements(&'a self) -> Vec<Cow<'a, str>> {
+        vec![]
+    }
+
     /// Maps `n` to a unique identifier with respect to `self`. The
     /// implementer is responsible for ensuring that the returned name
     /// is a valid DOT identifier.
     fn node_id(&'a self, n: &N) -> Id<'a>;
 
+    /// Fallible variant of `node_id`, used by `render_checked`. The
+    /// default just returns the output from `node_id`; override it to
+    /// report an invalid identifier instead of panicking.
+    #[allow(clippy::result_unit_err)]
+    fn try_node_id(&'a self, n: &N) -> Result<Id<'a>, ()> {
+        Ok(self.node_id(n))
+    }
+
     /// Maps `n` to one of the [graphviz `shape` names][1]. If `None`
-    /// is returned, no `shape` attribute is specified.
+    /// is returned, no `shape` attribute is specified. The name is
+    /// always written as a quoted string; an `HtmlStr` is not wrapped in
+    /// angle brackets, as `shape` does not accept HTML.
     ///
     /// [1]: https://graphviz.org/doc/info/shapes.html
     fn node_shape(&'a self, _node: &N) -> Option<LabelText<'a>> {
         None
     }
 
+    /// Maps `n` to one of the common shapes. If `Some` is returned, it
+    /// overrides `node_shape`, which remains available for the shapes
+    /// `Shape` does not cover.
+    fn node_shape_typed(&'a self, _n: &N) -> Option<Shape> {
+        None
+    }
+
     /// Maps `n` to a label that will be used in the rendered output.
     /// The label need not be unique, and may be the empty string; the
     /// default is just the output from `node_id`.
@@ -502,1129 +1056,5352 @@ pub trait Labeller<'a,N,E> {
         None
     }
 
-    /// Maps `n` to one of the [graphviz `color` names][1]. If `None`
-    /// is returned, no `color` attribute is specified.
+    /// Return an explicit way of drawing edges.
     ///
-    /// [1]: https://graphviz.gitlab.io/_pages/doc/info/colors.html
-    fn node_color(&'a self, _node: &N) -> Option<LabelText<'a>> {
+    /// Return 'None' to use the layout engine's default.
+    fn splines(&'a self) -> Option<Splines> {
         None
     }
 
-    /// Maps `n` to a set of arbritrary node attributes.
-    fn node_attrs(&'a self, _n: &N) -> HashMap<&str, &str> {
-        HashMap::default()
-    }
-
-    /// Maps `e` to arrow style that will be used on the end of an edge.
-    /// Defaults to default arrow style.
-    fn edge_end_arrow(&'a self, _e: &E) -> Arrow {
-        Arrow::default()
+    /// Whether to merge multiedges into a single edge and parallel
+    /// edge segments, defaults to `false`.
+    fn concentrate(&self) -> bool {
+        false
     }
 
-    /// Maps `e` to arrow style that will be used on the end of an edge.
-    /// Defaults to default arrow style.
-    fn edge_start_arrow(&'a self, _e: &E) -> Arrow {
-        Arrow::default()
+    /// Whether to rank the nodes of the whole graph at once, ignoring
+    /// clusters, which ranks edges between clusters correctly. Defaults
+    /// to `false`, in which case no `newrank` attribute is specified.
+    fn newrank(&self) -> bool {
+        false
     }
 
-    /// Maps `e` to a style that will be used in the rendered output.
-    fn edge_style(&'a self, _e: &E) -> Style {
-        Style::None
+    /// Return an explicit edge ordering constraint for the whole graph.
+    ///
+    /// Return 'None' to let the layout engine reorder edges freely.
+    fn ordering(&self) -> Option<Ordering> {
+        None
     }
 
-    /// Maps `e` to one of the [graphviz `color` names][1]. If `None`
-    /// is returned, no `color` attribute is specified.
+    /// The [graphviz `color` name][1] of the graph's background. If
+    /// `None` is returned, no `bgcolor` attribute is specified.
     ///
     /// [1]: https://graphviz.gitlab.io/_pages/doc/info/colors.html
-    fn edge_color(&'a self, _e: &E) -> Option<LabelText<'a>> {
+    fn bgcolor(&'a self) -> Option<LabelText<'a>> {
         None
     }
 
-    /// Maps `e` to a set of arbritrary edge attributes.
-    fn edge_attrs(&'a self, _e: &E) -> HashMap<&str, &str> {
-        HashMap::default()
+    /// A label for the graph as a whole. If `None` is returned, no
+    /// `label` attribute is specified.
+    fn graph_label(&'a self) -> Option<LabelText<'a>> {
+        None
     }
- 
-    /// The kind of graph, defaults to `Kind::Digraph`.
-    #[inline]
-    fn kind(&self) -> Kind {
-        Kind::Digraph
+
+    /// The vertical placement of the graph label. If `None` is
+    /// returned, no `labelloc` attribute is specified.
+    fn label_loc(&'a self) -> Option<LabelLoc> {
+        None
     }
-}
 
-/// Escape tags in such a way that it is suitable for inclusion in a
-/// Graphviz HTML label.
-pub fn escape_html(s: &str) -> String {
-    s
-        .replace("&", "&amp;")
-        .replace("\"", "&quot;")
-        .replace("<", "&lt;")
-        .replace(">", "&gt;")
-}
+    /// The horizontal placement of the graph label. If `None` is
+    /// returned, no `labeljust` attribute is specified.
+    fn label_just(&'a self) -> Option<LabelJust> {
+        None
+    }
 
-impl<'a> LabelText<'a> {
-    pub fn label<S:Into<Cow<'a, str>>>(s: S) -> LabelText<'a> {
-        LabelStr(s.into())
+    /// Whether to pack the disconnected components of the graph. If
+    /// `None` is returned, no `pack` attribute is specified.
+    fn pack(&self) -> Option<Pack> {
+        None
     }
 
-    pub fn escaped<S:Into<Cow<'a, str>>>(s: S) -> LabelText<'a> {
-        EscStr(s.into())
+    /// How to pack the disconnected components of the graph. If `None`
+    /// is returned, no `packmode` attribute is specified.
+    fn packmode(&self) -> Option<PackMode> {
+        None
     }
 
-    pub fn html<S: Into<Cow<'a, str>>>(s: S) -> LabelText<'a> {
-        HtmlStr(s.into())
+    /// The maximum width and height of the drawing, in inches. If
+    /// `None` is returned, no `size` attribute is specified.
+    fn size(&'a self) -> Option<(f64, f64)> {
+        None
     }
 
-    fn escape_char<F>(c: char, mut f: F)
-        where F: FnMut(char)
-    {
-        match c {
-            // not escaping \\, since Graphviz escString needs to
-            // interpret backslashes; see EscStr above.
-            '\\' => f(c),
-            _ => for c in c.escape_default() {
-                f(c)
-            },
-        }
+    /// How to fit the drawing to its `size`. If `None` is returned, no
+    /// `ratio` attribute is specified.
+    fn ratio(&self) -> Option<Ratio> {
+        None
     }
-    fn escape_str(s: &str) -> String {
-        let mut out = String::with_capacity(s.len());
-        for c in s.chars() {
-            LabelText::escape_char(c, |c| out.push(c));
-        }
-        out
+
+    /// The horizontal and vertical margin around the drawing, in
+    /// inches. If `None` is returned, no `margin` attribute is specified.
+    fn margin(&'a self) -> Option<(f64, f64)> {
+        None
     }
 
-    fn escape_default(s: &str) -> String {
-        s.chars().flat_map(|c| c.escape_default()).collect()
+    /// The rotation of the drawing in degrees. Graphviz only knows `90`,
+    /// for landscape mode, and `0`; other values are passed through as
+    /// is. If `None` is returned, no `rotate` attribute is specified.
+    fn rotate(&self) -> Option<u32> {
+        None
     }
 
-    /// Renders text as string suitable for a label in a .dot file.
-    /// This includes quotes or suitable delimeters.
-    pub fn to_dot_string(&self) -> String {
-        match self {
-            &LabelStr(ref s) => format!("\"{}\"", LabelText::escape_default(s)),
-            &EscStr(ref s) => format!("\"{}\"", LabelText::escape_str(&s[..])),
-            &HtmlStr(ref s) => format!("<{}>", s),
-        }
+    /// The font shared by the whole drawing. If `None` is returned, no
+    /// `fontname` or `fontsize` attribute is specified.
+    fn graph_font(&'a self) -> Option<Font<'a>> {
+        None
     }
 
-    /// Decomposes content into string suitable for making EscStr that
-    /// yields same content as self.  The result obeys the law
-    /// render(`lt`) == render(`EscStr(lt.pre_escaped_content())`) for
-    /// all `lt: LabelText`.
-    fn pre_escaped_content(self) -> Cow<'a, str> {
-        match self {
-            EscStr(s) => s,
-            LabelStr(s) => if s.contains('\\') {
-                LabelText::escape_default(&*s).into()
-            } else {
-                s
-            },
-            HtmlStr(s) => s,
-        }
+    /// The margin kept around nodes when removing overlaps, with the
+    /// `neato` and `fdp` layouts. If `None` is returned, no `sep`
+    /// attribute is specified.
+    fn sep(&self) -> Option<Sep> {
+        None
     }
 
-    /// Puts `prefix` on a line above this label, with a blank line separator.
-    pub fn prefix_line(self, prefix: LabelText) -> LabelText<'static> {
-        prefix.suffix_line(self)
+    /// The margin kept around nodes when splines are routed around
+    /// them; it should be smaller than `sep`. If `None` is returned, no
+    /// `esep` attribute is specified.
+    fn esep(&self) -> Option<Sep> {
+        None
     }
 
-    /// Puts `suffix` on a line below this label, with a blank line separator.
-    pub fn suffix_line(self, suffix: LabelText) -> LabelText<'static> {
-        let mut prefix = self.pre_escaped_content().into_owned();
-        let suffix = suffix.pre_escaped_content();
-        prefix.push_str(r"\n\n");
-        prefix.push_str(&suffix[..]);
-        EscStr(prefix.into())
+    /// How overlapping nodes are removed by the `neato`, `fdp` and
+    /// `sfdp` layouts. If `None` is returned, no `overlap` attribute is
+    /// specified.
+    fn overlap(&self) -> Option<Overlap> {
+        None
     }
-}
 
+    /// The resolution of bitmap output, in pixels per inch. If `None`
+    /// is returned, no `dpi` attribute is specified.
+    fn dpi(&self) -> Option<f64> {
+        None
+    }
 
-/// This structure holds all information that can describe an arrow connected to
-/// either start or end of an edge.
-#[derive(Clone, Hash, PartialEq, Eq)]
-pub struct Arrow {
-    pub arrows: Vec<ArrowShape>,
-}
+    /// The part of the drawing that is shown, as `"W,H,Z,x,y"` or
+    /// `"W,H,Z,N"`, i.e. the width and height of the final drawing in
+    /// points, a zoom factor, and the center as a point or a node name.
+    /// If `None` is returned, no `viewport` attribute is specified.
+    fn viewport(&'a self) -> Option<Cow<'a, str>> {
+        None
+    }
 
-use self::ArrowShape::*;
+    /// The names of the layers of the drawing, in order. Nodes and
+    /// edges are assigned to them with `node_layer` and `edge_layer`.
+    /// If `None` is returned, no `layers` attribute is specified.
+    fn layers(&'a self) -> Option<Vec<Cow<'a, str>>> {
+        None
+    }
 
-impl Arrow {
-    /// Return `true` if this is a default arrow.
-    fn is_default(&self) -> bool {
-        self.arrows.is_empty()
+    /// Maps `n` to one of the [graphviz `color` names][1]. If `None`
+    /// is returned, no `color` attribute is specified.
+    ///
+    /// [1]: https://graphviz.gitlab.io/_pages/doc/info/colors.html
+    fn node_color(&'a self, _node: &N) -> Option<LabelText<'a>> {
+        None
     }
 
-    /// Arrow constructor which returns a default arrow
-    pub fn default() -> Arrow {
-        Arrow {
-            arrows: vec![],
-        }
+    /// Maps `n` to the line its outline is drawn with. If `Some` is
+    /// returned, it overrides `node_color` and `node_style`.
+    fn node_pen(&'a self, _n: &N) -> Option<Pen<'a>> {
+        None
     }
 
-    /// Arrow constructor which returns an empty arrow
-    pub fn none() -> Arrow {
-        Arrow {
-            arrows: vec![NoArrow],
-        }
+    /// Maps `n` to the color of its label text, whatever the kind of
+    /// label. If `None` is returned, no `fontcolor` attribute is
+    /// specified.
+    fn node_fontcolor(&'a self, _n: &N) -> Option<LabelText<'a>> {
+        None
     }
 
-    /// Arrow constructor which returns a regular triangle arrow, without modifiers
-    pub fn normal() -> Arrow {
-        Arrow {
-            arrows: vec![ArrowShape::normal()]
-        }
+    /// Maps `n` to the path of an image file displayed inside the node.
+    /// If `None` is returned, no `image` attribute is specified.
+    fn node_image(&'a self, _n: &N) -> Option<Cow<'a, str>> {
+        None
     }
 
-    /// Arrow constructor which returns an arrow created by a given ArrowShape.
-    pub fn from_arrow(arrow: ArrowShape) -> Arrow {
-        Arrow {
-            arrows: vec![arrow],
-        }
+    /// Maps `n` to the position of its image within the node. If
+    /// `None` is returned, no `imagepos` attribute is specified.
+    fn node_imagepos(&'a self, _n: &N) -> Option<ImagePos> {
+        None
     }
 
-    /// Function which converts given arrow into a renderable form.
-    pub fn to_dot_string(&self) -> String {
-        let mut cow = String::new();
-        for arrow in &self.arrows {
-            cow.push_str(&arrow.to_dot_string());
-        };
-        cow
+    /// Maps `n` to its dimensions. If `None` is returned, no `width`,
+    /// `height` or `fixedsize` attribute is specified.
+    fn node_size(&'a self, _n: &N) -> Option<NodeSize> {
+        None
     }
-}
 
+    /// Maps `n` to the horizontal and vertical space left around its
+    /// label, in inches. If `None` is returned, no `margin` attribute
+    /// is specified.
+    fn node_margin(&'a self, _n: &N) -> Option<(f64, f64)> {
+        None
+    }
 
-impl Into<Arrow> for [ArrowShape; 2] {
-    fn into(self) -> Arrow {
-        Arrow {
-            arrows: vec![self[0], self[1]],
-        }
+    /// Maps `n` to the value of its DOT `id` attribute, which graphviz
+    /// uses as the `id` of the generated SVG element. This is distinct
+    /// from the identifier returned by `node_id`. If `None` is
+    /// returned, no `id` attribute is specified.
+    fn node_svg_id(&'a self, _n: &N) -> Option<Id<'a>> {
+        None
     }
-}
-impl Into<Arrow> for [ArrowShape; 3] {
-    fn into(self) -> Arrow {
-        Arrow {
-            arrows: vec![self[0], self[1], self[2]],
-        }
+
+    /// Maps `n` to a comment emitted on the line above its statement,
+    /// e.g. to note which object produced the node. A comment spanning
+    /// several lines is emitted as several `//` lines.
+    fn node_comment(&'a self, _n: &N) -> Option<Cow<'a, str>> {
+        None
     }
-}
-impl Into<Arrow> for [ArrowShape; 4] {
-    fn into(self) -> Arrow {
-        Arrow {
-            arrows: vec![self[0], self[1], self[2], self[3]],
-        }
+
+    /// Maps `n` to the value of its DOT `comment` attribute, which
+    /// graphviz embeds in output formats such as SVG or PostScript.
+    /// This is unrelated to `node_comment`. If `None` is returned, no
+    /// `comment` attribute is specified.
+    fn node_attr_comment(&'a self, _n: &N) -> Option<Cow<'a, str>> {
+        None
     }
-}
 
-/// Arrow modifier that determines if the shape is empty or filled.
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
-pub enum Fill {
-    Open,
-    Filled,
-}
+    /// Maps `n` to a group name; edges between nodes of the same group
+    /// are kept straight and the nodes aligned. If `None` is returned,
+    /// no `group` attribute is specified.
+    fn node_group(&'a self, _n: &N) -> Option<Id<'a>> {
+        None
+    }
 
-impl Fill {
-    pub fn as_slice(self) -> &'static str {
-        match self {
-            Fill::Open => "o",
-            Fill::Filled => "",
-        }
+    /// Maps `n` to the subgraph it is placed in. The nodes of each
+    /// subgraph are written together in a `subgraph` block, which
+    /// Graphviz draws as a cluster if its id starts with `cluster`. If
+    /// `None` is returned, the node is written at the top level.
+    fn node_subgraph(&'a self, _n: &N) -> Option<Id<'a>> {
+        None
     }
-}
 
-/// Arrow modifier that determines if the shape is clipped.
-/// For example `Side::Left` means only left side is visible.
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
-pub enum Side {
-    Left,
-    Right,
-    Both,
-}
+    /// Maps `n` to whether the lines of its label are justified against
+    /// the label itself rather than against the node, which matters for
+    /// lines ended with `\l` or `\r`. If `false` is returned, no
+    /// `nojustify` attribute is specified.
+    fn node_nojustify(&'a self, _n: &N) -> bool {
+        false
+    }
 
-impl Side {
-    pub fn as_slice(self) -> &'static str {
-        match self {
-            Side::Left  => "l",
-            Side::Right => "r",
-            Side::Both  => "",
-        }
+    /// Maps `n` to the layer, or range of layers, it is drawn in; see
+    /// `layers`. If `None` is returned, no `layer` attribute is
+    /// specified.
+    fn node_layer(&'a self, _n: &N) -> Option<Cow<'a, str>> {
+        None
     }
-}
 
+    /// Maps `n` to its position, e.g. as computed by an earlier run of
+    /// Graphviz, in points. A trailing `!`, as in `"1,2!"`, pins the
+    /// node to that position in neato. If `None` is returned, no `pos`
+    /// attribute is specified.
+    fn node_pos(&'a self, _n: &N) -> Option<Cow<'a, str>> {
+        None
+    }
 
-/// This enumeration represents all possible arrow edge
-/// as defined in [graphviz documentation](https://graphviz.org/doc/info/arrows.html).
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
-pub enum ArrowShape {
-    /// No arrow will be displayed
-    NoArrow,
-    /// Arrow that ends in a triangle. Basically a normal arrow.
-    /// NOTE: there is error in official documentation, this supports both fill and side clipping
-    Normal(Fill, Side),
-    /// Arrow ending in a small square box
-    Box(Fill, Side),
-    /// Arrow ending in a three branching lines also called crow's foot
-    Crow(Side),
-    /// Arrow ending in a curve
-    Curve(Side),
-    /// Arrow ending in an inverted curve
-    ICurve(Fill, Side),
-    /// Arrow ending in an diamond shaped rectangular shape.
-    Diamond(Fill, Side),
-    /// Arrow ending in a circle.
-    Dot(Fill),
-    /// Arrow ending in an inverted triangle.
-    Inv(Fill, Side),
-    /// Arrow ending with a T shaped arrow.
-    Tee(Side),
-    /// Arrow ending with a V shaped arrow.
-    Vee(Side),
-}
-impl ArrowShape {
-    /// Constructor which returns no arrow.
-    pub fn none() -> ArrowShape {
-        ArrowShape::NoArrow
+    /// Maps `n` to the angle, in degrees, of its gradient fill. For a
+    /// `Style::Radial` fill it moves the center of the gradient. If
+    /// `None` is returned, no `gradientangle` attribute is specified.
+    fn node_gradientangle(&'a self, _n: &N) -> Option<f64> {
+        None
     }
 
-    /// Constructor which returns normal arrow.
-    pub fn normal() -> ArrowShape {
-        ArrowShape::Normal(Fill::Filled, Side::Both)
+    /// Maps `n` to a set of arbritrary node attributes.
+    fn node_attrs(&'a self, _n: &N) -> HashMap<Cow<'a, str>, Cow<'a, str>> {
+        HashMap::default()
     }
 
-    /// Constructor which returns a regular box arrow.
-    pub fn boxed() -> ArrowShape {
-        ArrowShape::Box(Fill::Filled, Side::Both)
+    /// Maps `e` to arrow style that will be used on the end of an edge.
+    /// Defaults to default arrow style. Return `Arrow::none()` to drop
+    /// the arrowhead of a single edge.
+    fn edge_end_arrow(&'a self, _e: &E) -> Arrow {
+        Arrow::default()
     }
 
-    /// Constructor which returns a regular crow arrow.
-    pub fn crow() -> ArrowShape {
-        ArrowShape::Crow(Side::Both)
+    /// Maps `e` to arrow style that will be used on the end of an edge.
+    /// Defaults to default arrow style.
+    fn edge_start_arrow(&'a self, _e: &E) -> Arrow {
+        Arrow::default()
     }
 
-    /// Constructor which returns a regular curve arrow.
-    pub fn curve() -> ArrowShape {
-        ArrowShape::Curve(Side::Both)
+    /// Maps `e` to the ends at which its arrowheads are drawn. If `None`
+    /// is returned, `dir="both"` is implied whenever `edge_start_arrow`
+    /// is not the default arrow. Otherwise the returned direction is
+    /// emitted as is and decides which of the `edge_start_arrow` and
+    /// `edge_end_arrow` shapes are actually drawn. On a digraph,
+    /// `RenderOption::NoArrows` takes precedence.
+    fn edge_dir(&'a self, _e: &E) -> Option<Dir> {
+        None
     }
 
-    /// Constructor which returns an inverted curve arrow.
-    pub fn icurve() -> ArrowShape {
-        ArrowShape::ICurve(Fill::Filled, Side::Both)
+    /// Maps `e` to a scale factor for its arrowheads, independent of
+    /// their shape. If `None` is returned, no `arrowsize` attribute is
+    /// specified.
+    fn edge_arrowsize(&'a self, _e: &E) -> Option<f64> {
+        None
     }
 
-    /// Constructor which returns a diamond arrow.
-    pub fn diamond() -> ArrowShape {
-        ArrowShape::Diamond(Fill::Filled, Side::Both)
+    /// Maps `e` to the distance of its head and tail labels from the
+    /// ends of the edge, as a factor of the default. If `None` is
+    /// returned, no `labeldistance` attribute is specified.
+    fn edge_labeldistance(&'a self, _e: &E) -> Option<f64> {
+        None
     }
 
-    /// Constructor which returns a circle shaped arrow.
-    pub fn dot() -> ArrowShape {
-        ArrowShape::Diamond(Fill::Filled, Side::Both)
+    /// Maps `e` to the angle, in degrees, by which its head and tail
+    /// labels are rotated around the ends of the edge. If `None` is
+    /// returned, no `labelangle` attribute is specified.
+    fn edge_labelangle(&'a self, _e: &E) -> Option<f64> {
+        None
     }
 
-    /// Constructor which returns an inverted triangle arrow.
-    pub fn inv() -> ArrowShape {
-        ArrowShape::Inv(Fill::Filled, Side::Both)
+    /// Maps `e` to whether a line connects its label to the edge, which
+    /// helps to tell which edge a label belongs to in a dense graph. If
+    /// `None` is returned, no `decorate` attribute is specified.
+    fn edge_decorate(&'a self, _e: &E) -> Option<bool> {
+        None
     }
 
-    /// Constructor which returns a T shaped arrow.
-    pub fn tee() -> ArrowShape {
-        ArrowShape::Tee(Side::Both)
+    /// Maps `e` to whether its label may overlap other edges and
+    /// labels. If `None` is returned, no `labelfloat` attribute is
+    /// specified.
+    fn edge_labelfloat(&'a self, _e: &E) -> Option<bool> {
+        None
     }
 
-    /// Constructor which returns a V shaped arrow.
-    pub fn vee() -> ArrowShape {
-        ArrowShape::Vee(Side::Both)
+    /// Maps `e` to a style that will be used in the rendered output.
+    fn edge_style(&'a self, _e: &E) -> Style {
+        Style::None
     }
 
-    /// Function which renders given ArrowShape into a String for displaying.
-    pub fn to_dot_string(&self) -> String {
-        let mut res = String::new();
-        match *self {
-            Box(fill, side) | ICurve(fill, side)| Diamond(fill, side) |
-            Inv(fill, side) | Normal(fill, side)=> {
-                res.push_str(fill.as_slice());
-                match side {
-                    Side::Left | Side::Right => res.push_str(side.as_slice()),
-                    Side::Both => {},
-                };
-            },
-            Dot(fill)       => res.push_str(fill.as_slice()),
-            Crow(side) | Curve(side) | Tee(side)
-            | Vee(side) => {
-                match side {
-                    Side::Left | Side::Right => res.push_str(side.as_slice()),
-                    Side::Both => {},
-                }
-            }
-            NoArrow => {},
-        };
-        match *self {
-            NoArrow         => res.push_str("none"),
-            Normal(_, _)    => res.push_str("normal"),
-            Box(_, _)       => res.push_str("box"),
-            Crow(_)         => res.push_str("crow"),
-            Curve(_)        => res.push_str("curve"),
-            ICurve(_, _)    => res.push_str("icurve"),
-            Diamond(_, _)   => res.push_str("diamond"),
-            Dot(_)          => res.push_str("dot"),
-            Inv(_, _)       => res.push_str("inv"),
-            Tee(_)          => res.push_str("tee"),
-            Vee(_)          => res.push_str("vee"),
-        };
-        res
+    /// Maps `e` to one of the [graphviz `color` names][1]. If `None`
+    /// is returned, no `color` attribute is specified.
+    ///
+    /// [1]: https://graphviz.gitlab.io/_pages/doc/info/colors.html
+    fn edge_color(&'a self, _e: &E) -> Option<LabelText<'a>> {
+        None
     }
-}
 
-pub type Nodes<'a,N> = Cow<'a,[N]>;
-pub type Edges<'a,E> = Cow<'a,[E]>;
+    /// Maps `e` to a list of colors, drawn as parallel edges or as
+    /// segments of a gradient. If the list is non-empty it overrides
+    /// `edge_color`, and the colors are emitted as a single
+    /// colon-separated `color` attribute.
+    fn edge_colors(&'a self, _e: &E) -> Vec<LabelText<'a>> {
+        vec![]
+    }
 
-/// Graph kind determines if `digraph` or `graph` is used as keyword
-/// for the graph.
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
-pub enum Kind {
-    Digraph,
-    Graph,
-}
+    /// Maps `e` to the line it is drawn with. If `Some` is returned, it
+    /// overrides `edge_color`, `edge_colors` and `edge_style`.
+    fn edge_pen(&'a self, _e: &E) -> Option<Pen<'a>> {
+        None
+    }
 
-impl Kind {
-    /// The keyword to use to introduce the graph.
-    /// Determines which edge syntax must be used, and default style.
-    fn keyword(&self) -> &'static str {
-        match *self {
-            Kind::Digraph => "digraph",
-            Kind::Graph => "graph"
-        }
+    /// Maps `e` to the color of its label text, like `node_fontcolor`.
+    /// If `None` is returned, no `fontcolor` attribute is specified.
+    fn edge_fontcolor(&'a self, _e: &E) -> Option<LabelText<'a>> {
+        None
     }
 
-    /// The edgeop syntax to use for this graph kind.
-    fn edgeop(&self) -> &'static str {
-        match *self {
-            Kind::Digraph => "->",
-            Kind::Graph => "--",
-        }
+    /// Maps `e` to whether it is used in ranking the nodes. If `None`
+    /// is returned, no `constraint` attribute is specified.
+    fn edge_constraint(&'a self, _e: &E) -> Option<bool> {
+        None
+    }
+
+    /// Maps `e` to the value of its DOT `id` attribute, which graphviz
+    /// uses as the `id` of the generated SVG element. If `None` is
+    /// returned, no `id` attribute is specified.
+    fn edge_svg_id(&'a self, _e: &E) -> Option<Id<'a>> {
+        None
+    }
+
+    /// Maps `e` to a group name; edges of the same group with the same
+    /// target share the point where their heads attach. If `None` is
+    /// returned, no `samehead` attribute is specified.
+    fn edge_samehead(&'a self, _e: &E) -> Option<Id<'a>> {
+        None
+    }
+
+    /// Maps `e` to a group name; edges of the same group with the same
+    /// source share the point where their tails attach. If `None` is
+    /// returned, no `sametail` attribute is specified.
+    fn edge_sametail(&'a self, _e: &E) -> Option<Id<'a>> {
+        None
+    }
+
+    /// Maps `e` to a comment emitted on the line above its statement.
+    /// A comment spanning several lines is emitted as several `//`
+    /// lines.
+    fn edge_comment(&'a self, _e: &E) -> Option<Cow<'a, str>> {
+        None
+    }
+
+    /// Maps `e` to the value of its DOT `comment` attribute, which
+    /// graphviz embeds in output formats such as SVG or PostScript.
+    /// This is unrelated to `edge_comment`. If `None` is returned, no
+    /// `comment` attribute is specified.
+    fn edge_attr_comment(&'a self, _e: &E) -> Option<Cow<'a, str>> {
+        None
+    }
+
+    /// Maps `e` to whether the lines of its label are justified against
+    /// the label itself, like `node_nojustify`. If `false` is returned,
+    /// no `nojustify` attribute is specified.
+    fn edge_nojustify(&'a self, _e: &E) -> bool {
+        false
+    }
+
+    /// Maps `e` to the layer, or range of layers, it is drawn in; see
+    /// `layers`. If `None` is returned, no `layer` attribute is
+    /// specified.
+    fn edge_layer(&'a self, _e: &E) -> Option<Cow<'a, str>> {
+        None
+    }
+
+    /// Maps `e` to the control points of its spline, e.g. as computed by
+    /// an earlier run of Graphviz. If `None` is returned, no `pos`
+    /// attribute is specified.
+    fn edge_pos(&'a self, _e: &E) -> Option<Cow<'a, str>> {
+        None
+    }
+
+    /// Maps `e` to a set of arbritrary edge attributes.
+    fn edge_attrs(&'a self, _e: &E) -> HashMap<Cow<'a, str>, Cow<'a, str>> {
+        HashMap::default()
+    }
+ 
+    /// The kind of graph, defaults to `Kind::Digraph`.
+    #[inline]
+    fn kind(&self) -> Kind {
+        Kind::Digraph
     }
 }
 
-// (The type parameters in GraphWalk should be associated items,
-// when/if Rust supports such.)
+/// Escape tags in such a way that it is suitable for inclusion in a
+/// Graphviz HTML label. An `&` that already starts a well-formed entity,
+/// such as `&amp;` or `&#169;`, is kept, so escaping text twice does
+/// not double its entities.
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for (i, c) in s.char_indices() {
+        match c {
+            '&' if starts_with_entity(&s[i..]) => out.push('&'),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
 
-/// GraphWalk is an abstraction over a graph = (nodes,edges)
-/// made up of node handles `N` and edge handles `E`, where each `E`
-/// can be mapped to its source and target nodes.
-///
-/// The lifetime parameter `'a` is exposed in this trait (rather than
-/// introduced as a generic parameter on each method declaration) so
-/// that a client impl can choose `N` and `E` that have substructure
-/// that is bound by the self lifetime `'a`.
-///
-/// The `nodes` and `edges` method each return instantiations of
-/// `Cow<[T]>` to leave implementers the freedom to create
-/// entirely new vectors or to pass back slices into internally owned
-/// vectors.
-pub trait GraphWalk<'a, N: Clone, E: Clone> {
-    /// Returns all the nodes in this graph.
-    fn nodes(&'a self) -> Nodes<'a, N>;
-    /// Returns all of the edges in this graph.
-    fn edges(&'a self) -> Edges<'a, E>;
-    /// The source node for `edge`.
-    fn source(&'a self, edge: &E) -> N;
-    /// The target node for `edge`.
-    fn target(&'a self, edge: &E) -> N;
+/// Escapes every `&`, unlike `escape_html`, for text that is shown
+/// literally.
+fn escape_html_literal(s: &str) -> String {
+    s
+        .replace("&", "&amp;")
+        .replace("\"", "&quot;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
-pub enum RenderOption {
-    NoEdgeLabels,
-    NoNodeLabels,
-    NoEdgeStyles,
-    NoEdgeColors,
-    NoNodeStyles,
-    NoNodeColors,
-    NoArrows,
+/// Returns `true` if `s` starts with a named or numeric character
+/// reference, e.g. `&lt;`, `&#60;` or `&#x3C;`.
+fn starts_with_entity(s: &str) -> bool {
+    let end = match s.find(';') {
+        Some(end) => end,
+        None => return false,
+    };
+    let name = &s[1..end];
+    match name.strip_prefix('#') {
+        Some(num) => match num.strip_prefix(['x', 'X']) {
+            Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()),
+        },
+        None => {
+            name.starts_with(|c: char| c.is_ascii_alphabetic())
+                && name.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+    }
 }
 
-/// Returns vec holding all the default render options.
-pub fn default_options() -> Vec<RenderOption> {
-    vec![]
+/// Escapes `s` the way the text of a `LabelStr` is escaped, without the
+/// surrounding quotes: quotes, backslashes and control characters are
+/// escaped, and non-ASCII characters are kept as they are.
+pub fn escape_label_string(s: &str) -> String {
+    LabelText::escape_default(s)
 }
 
-/// Renders graph `g` into the writer `w` in DOT syntax.
-/// (Simple wrapper around `render_opts` that passes a default set of options.)
-pub fn render<'a,
-              N: Clone + 'a,
-              E: Clone + 'a,
-              G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
-              W: Write>
-    (g: &'a G,
-     w: &mut W)
-     -> io::Result<()> {
-    render_opts(g, w, &[])
+/// Escapes `s` the way the text of an `EscStr` is escaped, without the
+/// surrounding quotes: backslashes are kept, so that escape sequences
+/// such as `\l` reach Graphviz unchanged.
+pub fn escape_escstring(s: &str) -> String {
+    LabelText::escape_str(s)
 }
 
-/// Renders graph `g` into the writer `w` in DOT syntax.
-/// (Main entry point for the library.)
-pub fn render_opts<'a,
-                   N: Clone + 'a,
-                   E: Clone + 'a,
-                   G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
-                   W: Write>
-    (g: &'a G,
-     w: &mut W,
-     options: &[RenderOption])
-     -> io::Result<()> {
-    fn writeln<W: Write>(w: &mut W, arg: &[&str]) -> io::Result<()> {
-        for &s in arg {
-            w.write_all(s.as_bytes())?;
+/// Escapes the characters that delimit the fields and ports of a record
+/// label, and backslashes, so that `s` is rendered literally inside an
+/// escString.
+fn escape_record(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if let '{' | '}' | '|' | '<' | '>' | '\\' = c {
+            out.push('\\');
         }
-        write!(w, "\n")
+        out.push(c);
     }
+    out
+}
 
-    fn indent<W: Write>(w: &mut W) -> io::Result<()> {
-        w.write_all(b"    ")
+impl<'a> LabelText<'a> {
+    pub fn label<S:Into<Cow<'a, str>>>(s: S) -> LabelText<'a> {
+        LabelStr(s.into())
     }
 
-    writeln(w, &[g.kind().keyword(), " ", g.graph_id().as_slice(), " {"])?;
-    if g.kind() == Kind::Digraph {
-        if let Some(rankdir) = g.rank_dir() {
-            indent(w)?;
-            writeln(w, &["rankdir=\"", rankdir.as_slice(), "\";"])?;
-        }
+    pub fn escaped<S:Into<Cow<'a, str>>>(s: S) -> LabelText<'a> {
+        EscStr(s.into())
     }
 
-    for (name, value) in g.graph_attrs().iter() {
-        writeln(w, &[name, "=", value])?;
+    /// Like `escaped`, but only keeps the backslashes that start one of
+    /// the escape sequences `\n`, `\l`, `\r`, `\\`, `\"`, `\{`, `\}`,
+    /// `\|`, `\<` and `\>`. Any other backslash is doubled so that it
+    /// shows up as is, e.g. in a Windows path such as `C:\path`.
+    pub fn escaped_strict<S:Into<Cow<'a, str>>>(s: S) -> LabelText<'a> {
+        let s = s.into();
+        if !s.contains('\\') {
+            return EscStr(s);
+        }
+        let mut out = String::with_capacity(s.len() + 1);
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.peek() {
+                // quotes are escaped again by `to_dot_string`.
+                Some(&'"') => {}
+                Some(&next) if "nlr\\{}|<>".contains(next) => out.push(c),
+                _ => {
+                    out.push_str("\\\\");
+                    continue;
+                }
+            }
+            out.extend(chars.next());
+        }
+        EscStr(out.into())
     }
-    for n in g.nodes().iter() {
-        let colorstring;
 
-        indent(w)?;
-        let id = g.node_id(n);
+    /// Makes an `EscStr` that renders `s` literally, with the braces,
+    /// bars and angle brackets escaped so that they are not read as
+    /// record delimiters, e.g. by the `xdot` output or by a node with a
+    /// `record` shape.
+    pub fn record_safe(s: &str) -> LabelText<'static> {
+        EscStr(escape_record(s).into())
+    }
 
-        let escaped = &g.node_label(n).to_dot_string();
-        let shape;
+    pub fn html<S: Into<Cow<'a, str>>>(s: S) -> LabelText<'a> {
+        HtmlStr(s.into())
+    }
 
-        let mut text = vec![id.as_slice()];
+    /// Creates an HTML label from markup that is used as is, like
+    /// `LabelText::html`.
+    pub fn html_raw<S: Into<Cow<'a, str>>>(s: S) -> LabelText<'a> {
+        HtmlStr(s.into())
+    }
 
-        if !options.contains(&RenderOption::NoNodeLabels) {
-            text.push("[label=");
-            text.push(escaped);
-            text.push("]");
-        }
+    /// Creates an HTML label showing the plain text `s`, which is
+    /// escaped with `escape_html`, so entities already in `s` are kept.
+    pub fn html_escaped(s: &str) -> LabelText<'static> {
+        HtmlStr(escape_html(s).into())
+    }
 
-        let style = g.node_style(n);
-        if !options.contains(&RenderOption::NoNodeStyles) && style != Style::None {
-            text.push("[style=\"");
-            text.push(style.as_slice());
-            text.push("\"]");
+    fn escape_char<F>(c: char, mut f: F)
+        where F: FnMut(char)
+    {
+        match c {
+            // not escaping \\, since Graphviz escString needs to
+            // interpret backslashes; see EscStr above.
+            '\\' => f(c),
+            _ => LabelText::escape_ascii_char(c, f),
         }
+    }
 
-        let color = g.node_color(n);
-        if !options.contains(&RenderOption::NoNodeColors) {
-            if let Some(c) = color {
-                colorstring = c.to_dot_string();
-                text.push("[color=");
-                text.push(&colorstring);
-                text.push("]");
+    /// Escapes `c` like `char::escape_default`, but only if it is ASCII.
+    /// Graphviz reads UTF-8 and knows no `\u{...}` escapes, so any other
+    /// character, including combining marks, is passed through as is.
+    fn escape_ascii_char<F>(c: char, mut f: F)
+        where F: FnMut(char)
+    {
+        if c.is_ascii() {
+            for c in c.escape_default() {
+                f(c)
             }
+        } else {
+            f(c)
         }
-
-        if let Some(s) = g.node_shape(n) {
-            shape = s.to_dot_string();
-            text.push("[shape=");
-            text.push(&shape);
-            text.push("]");
+    }
+    fn escape_str(s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+        for c in s.chars() {
+            LabelText::escape_char(c, |c| out.push(c));
         }
+        out
+    }
 
-        let node_attrs = g.node_attrs(n).iter().map(|(name, value)| format!("[{name}={value}]")).collect::<Vec<String>>();
-        text.extend(node_attrs.iter().map(|s| s as &str));
+    fn escape_default(s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+        for c in s.chars() {
+            LabelText::escape_ascii_char(c, |c| out.push(c));
+        }
+        out
+    }
 
-        text.push(";");
-        writeln(w, &text)?;
+    /// Renders text as string suitable for a label in a .dot file.
+    /// This includes quotes or suitable delimeters.
+    pub fn to_dot_string(&self) -> String {
+        match *self {
+            LabelStr(ref s) => format!("\"{}\"", LabelText::escape_default(s)),
+            EscStr(ref s) => format!("\"{}\"", LabelText::escape_str(&s[..])),
+            HtmlStr(ref s) => format!("<{}>", s),
+        }
     }
 
-    for e in g.edges().iter() {
-        let colorstring;
-        let escaped_label = &g.edge_label(e).to_dot_string();
-        let start_arrow = g.edge_start_arrow(e);
-        let end_arrow = g.edge_end_arrow(e);
-        let start_arrow_s = start_arrow.to_dot_string();
-        let end_arrow_s = end_arrow.to_dot_string();
+    /// Decomposes content into string suitable for making EscStr that
+    /// yields same content as self.  The result obeys the law
+    /// render(`lt`) == render(`EscStr(lt.pre_escaped_content())`) for
+    /// all `lt: LabelText`.
+    fn pre_escaped_content(self) -> Cow<'a, str> {
+        match self {
+            EscStr(s) => s,
+            LabelStr(s) => if s.contains('\\') {
+                LabelText::escape_default(&s).into()
+            } else {
+                s
+            },
+            HtmlStr(s) => s,
+        }
+    }
 
-        indent(w)?;
-        let source = g.source(e);
-        let target = g.target(e);
-        let source_id = g.node_id(&source);
-        let target_id = g.node_id(&target);
+    /// Puts `prefix` on a line above this label, with a blank line separator.
+    pub fn prefix_line(self, prefix: LabelText) -> LabelText<'static> {
+        prefix.suffix_line(self)
+    }
 
-        let mut text = vec![source_id.as_slice(), " ",
-                            g.kind().edgeop(), " ",
-                            target_id.as_slice()];
+    /// Puts `suffix` on a line below this label, with a blank line separator.
+    pub fn suffix_line(self, suffix: LabelText) -> LabelText<'static> {
+        let mut prefix = self.pre_escaped_content().into_owned();
+        let suffix = suffix.pre_escaped_content();
+        prefix.push_str(r"\n\n");
+        prefix.push_str(&suffix[..]);
+        EscStr(prefix.into())
+    }
 
-        if !options.contains(&RenderOption::NoEdgeLabels) {
-            text.push("[label=");
-            text.push(escaped_label);
-            text.push("]");
-        }
+    /// Ends the last line of this label with `\l`, left-justifying it.
+    pub fn left_justify_line(self) -> LabelText<'static> {
+        self.end_line(r"\l")
+    }
 
-        let style = g.edge_style(e);
-        if !options.contains(&RenderOption::NoEdgeStyles) && style != Style::None {
-            text.push("[style=\"");
-            text.push(style.as_slice());
-            text.push("\"]");
-        }
+    /// Ends the last line of this label with `\r`, right-justifying it.
+    pub fn right_justify_line(self) -> LabelText<'static> {
+        self.end_line(r"\r")
+    }
 
-        let color = g.edge_color(e);
-        if !options.contains(&RenderOption::NoEdgeColors) {
-            if let Some(c) = color {
-                colorstring = c.to_dot_string();
-                text.push("[color=");
-                text.push(&colorstring);
-                text.push("]");
-            }
-        }
+    /// Ends the last line of this label with `\n`, centering it.
+    pub fn center_line(self) -> LabelText<'static> {
+        self.end_line(r"\n")
+    }
 
-        if !options.contains(&RenderOption::NoArrows) &&
-            (!start_arrow.is_default() || !end_arrow.is_default()) {
-            text.push("[");
-            if !end_arrow.is_default() {
-                text.push("arrowhead=\"");
-                text.push(&end_arrow_s);
-                text.push("\"");
+    /// Breaks `text` at word boundaries into left-justified lines of at
+    /// most `width` characters; a word longer than `width` gets a line
+    /// of its own.
+    pub fn wrap(text: &str, width: usize) -> LabelText<'static> {
+        let mut content = String::with_capacity(text.len() + 2);
+        let mut line_len = 0;
+        for word in text.split_whitespace() {
+            let len = word.chars().count();
+            if line_len > 0 && line_len + 1 + len > width {
+                content.push_str(r"\l");
+                line_len = 0;
             }
-            if !start_arrow.is_default() {
-                text.push(" dir=\"both\" arrowtail=\"");
-                text.push(&start_arrow_s);
-                text.push("\"");
+            if line_len > 0 {
+                content.push(' ');
+                line_len += 1;
             }
-
-            text.push("]");
+            content.push_str(&LabelText::label(word).pre_escaped_content());
+            line_len += len;
         }
-        let edge_attrs = g.edge_attrs(e).iter().map(|(name, value)| format!("[{name}={value}]")).collect::<Vec<String>>();
-        text.extend(edge_attrs.iter().map(|s| s as &str));
-        text.push(";");
-        writeln(w, &text)?;
+        content.push_str(r"\l");
+        EscStr(content.into())
     }
 
-    writeln(w, &["}"])
+    fn end_line(self, escape: &str) -> LabelText<'static> {
+        let mut content = self.pre_escaped_content().into_owned();
+        content.push_str(escape);
+        EscStr(content.into())
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use self::NodeLabels::*;
-    use super::{Id, Labeller, Nodes, Edges, GraphWalk, render, Style, Kind, RankDir};
-    use super::LabelText::{self, LabelStr, EscStr, HtmlStr};
-    use super::{Arrow, ArrowShape, Side};
-    use std::io;
-    use std::io::prelude::*;
+/// Builder for a graphviz [HTML-like label][html] laid out as a table.
+/// The text of each cell is escaped in full, including any `&` that
+/// starts an entity, so it is rendered literally.
+///
+/// [html]: https://graphviz.org/doc/info/shapes.html#html
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HtmlLabel {
+    rows: Vec<Vec<HtmlCell>>,
+}
 
-    /// each node is an index in a vector in the graph.
-    type Node = usize;
-    struct Edge {
-        from: usize,
-        to: usize,
-        label: &'static str,
-        style: Style,
-        start_arrow: Arrow,
-        end_arrow: Arrow,
-        color: Option<&'static str>,
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct HtmlCell {
+    text: String,
+    port: Option<String>,
+}
+
+impl HtmlLabel {
+    /// Starts a new table without any rows.
+    pub fn table() -> HtmlLabel {
+        HtmlLabel::default()
     }
 
-    fn edge(from: usize, to: usize, label: &'static str, style: Style, color: Option<&'static str>) -> Edge {
-        Edge {
-            from: from,
-            to: to,
-            label: label,
-            style: style,
-            start_arrow: Arrow::default(),
-            end_arrow: Arrow::default(),
-            color: color,
+    /// Starts a new row; subsequent cells are added to it.
+    pub fn row(mut self) -> HtmlLabel {
+        self.rows.push(vec![]);
+        self
+    }
+
+    /// Adds a cell containing `text` to the current row.
+    pub fn cell<S: AsRef<str>>(self, text: S) -> HtmlLabel {
+        self.push_cell(HtmlCell { text: escape_html_literal(text.as_ref()), port: None })
+    }
+
+    /// Adds a cell containing `text` to the current row, with a `PORT`
+    /// name that edges can attach to.
+    pub fn port_cell<P: AsRef<str>, S: AsRef<str>>(self, port: P, text: S) -> HtmlLabel {
+        self.push_cell(HtmlCell {
+            text: escape_html_literal(text.as_ref()),
+            port: Some(escape_html_literal(port.as_ref())),
+        })
+    }
 
+    fn push_cell(mut self, cell: HtmlCell) -> HtmlLabel {
+        if self.rows.is_empty() {
+            self.rows.push(vec![]);
         }
+        self.rows.last_mut().unwrap().push(cell);
+        self
     }
 
-    fn edge_with_arrows(from: usize, to: usize, label: &'static str, style:Style,
-        start_arrow: Arrow, end_arrow: Arrow, color: Option<&'static str>) -> Edge {
-        Edge {
-            from: from,
-            to: to,
-            label: label,
-            style: style,
-            start_arrow: start_arrow,
-            end_arrow: end_arrow,
-            color: color,
+    /// Renders the table as the contents of an HTML label, without the
+    /// enclosing `<` and `>`.
+    pub fn to_html(&self) -> String {
+        let mut res = String::from("<TABLE>");
+        for row in &self.rows {
+            res.push_str("<TR>");
+            for cell in row {
+                match cell.port {
+                    Some(ref port) => res.push_str(&format!("<TD PORT=\"{}\">", port)),
+                    None => res.push_str("<TD>"),
+                }
+                res.push_str(&cell.text);
+                res.push_str("</TD>");
+            }
+            res.push_str("</TR>");
         }
+        res.push_str("</TABLE>");
+        res
     }
 
+    /// Converts the table into a `LabelText::HtmlStr`.
+    pub fn into_label(self) -> LabelText<'static> {
+        HtmlStr(self.to_html().into())
+    }
+}
 
-    struct LabelledGraph {
-        /// The name for this graph. Used for labelling generated `digraph`.
-        name: &'static str,
+/// Builder for a graphviz [record label][record], made of fields that
+/// can carry a port name edges can attach to. The text and port of each
+/// field are escaped with `escape_record`, so they are rendered
+/// literally. Nested records are laid out across their parent.
+///
+/// [record]: https://graphviz.org/doc/info/shapes.html#record
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct RecordLabel {
+    fields: Vec<RecordField>,
+    flipped: bool,
+}
 
-        /// Each node is an index into `node_labels`; these labels are
-        /// used as the label text for each node. (The node *names*,
-        /// which are unique identifiers, are derived from their index
-        /// in this array.)
-        ///
-        /// If a node maps to None here, then just use its name as its
-        /// text.
-        node_labels: Vec<Option<&'static str>>,
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum RecordField {
+    Text { port: Option<String>, text: String },
+    Nested(RecordLabel),
+}
 
-        node_styles: Vec<Style>,
+impl RecordLabel {
+    /// Starts a new record without any fields.
+    pub fn new() -> RecordLabel {
+        RecordLabel::default()
+    }
 
-        /// Each edge relates a from-index to a to-index along with a
-        /// label; `edges` collects them.
-        edges: Vec<Edge>,
+    /// Adds a field containing `text`.
+    pub fn text<S: AsRef<str>>(mut self, text: S) -> RecordLabel {
+        self.fields.push(RecordField::Text { port: None, text: escape_record(text.as_ref()) });
+        self
     }
 
-    // A simple wrapper around LabelledGraph that forces the labels to
-    // be emitted as EscStr.
-    struct LabelledGraphWithEscStrs {
-        graph: LabelledGraph,
+    /// Adds a field containing `text`, with a port name that edges can
+    /// attach to.
+    pub fn field<P: AsRef<str>, S: AsRef<str>>(mut self, port: P, text: S) -> RecordLabel {
+        self.fields.push(RecordField::Text {
+            port: Some(escape_record(port.as_ref())),
+            text: escape_record(text.as_ref()),
+        });
+        self
     }
 
-    enum NodeLabels<L> {
-        AllNodesLabelled(Vec<L>),
-        UnlabelledNodes(usize),
-        SomeNodesLabelled(Vec<Option<L>>),
+    /// Adds `record` as a field, with its fields laid out across the
+    /// fields of this record.
+    pub fn nested(mut self, record: RecordLabel) -> RecordLabel {
+        self.fields.push(RecordField::Nested(record));
+        self
     }
 
-    type Trivial = NodeLabels<&'static str>;
+    /// Lays the fields out top to bottom in a graph with the given
+    /// `rank_dir`. Graphviz lays them out left to right unless the rank
+    /// direction is `LeftRight` or `RightLeft`.
+    pub fn vertical(mut self, rank_dir: Option<RankDir>) -> RecordLabel {
+        self.flipped = !RecordLabel::is_sideways(rank_dir);
+        self
+    }
 
-    impl NodeLabels<&'static str> {
-        fn into_opt_strs(self) -> Vec<Option<&'static str>> {
-            match self {
-                UnlabelledNodes(len) => vec![None; len],
-                AllNodesLabelled(lbls) => lbls.into_iter().map(|l| Some(l)).collect(),
-                SomeNodesLabelled(lbls) => lbls.into_iter().collect(),
-            }
-        }
+    /// Lays the fields out left to right in a graph with the given
+    /// `rank_dir`; see `vertical`.
+    pub fn horizontal(mut self, rank_dir: Option<RankDir>) -> RecordLabel {
+        self.flipped = RecordLabel::is_sideways(rank_dir);
+        self
+    }
 
-        fn len(&self) -> usize {
-            match self {
-                &UnlabelledNodes(len) => len,
-                &AllNodesLabelled(ref lbls) => lbls.len(),
-                &SomeNodesLabelled(ref lbls) => lbls.len(),
-            }
-        }
+    fn is_sideways(rank_dir: Option<RankDir>) -> bool {
+        matches!(rank_dir, Some(RankDir::LeftRight) | Some(RankDir::RightLeft))
     }
 
-    impl LabelledGraph {
-        fn new(name: &'static str,
-               node_labels: Trivial,
-               edges: Vec<Edge>,
-               node_styles: Option<Vec<Style>>)
-               -> LabelledGraph {
-            let count = node_labels.len();
-            LabelledGraph {
-                name: name,
-                node_labels: node_labels.into_opt_strs(),
-                edges: edges,
-                node_styles: match node_styles {
-                    Some(nodes) => nodes,
-                    None => vec![Style::None; count],
-                },
-            }
-        }
+    fn fields_to_record(&self) -> String {
+        let fields: Vec<String> = self.fields.iter().map(|field| match *field {
+            RecordField::Text { port: Some(ref port), ref text } => format!("<{}> {}", port, text),
+            RecordField::Text { port: None, ref text } => text.clone(),
+            RecordField::Nested(ref record) => format!("{{{}}}", record.fields_to_record()),
+        }).collect();
+        fields.join("|")
     }
 
-    impl LabelledGraphWithEscStrs {
-        fn new(name: &'static str,
-               node_labels: Trivial,
-               edges: Vec<Edge>)
-               -> LabelledGraphWithEscStrs {
-            LabelledGraphWithEscStrs { graph: LabelledGraph::new(name, node_labels, edges, None) }
+    /// Renders the record as the contents of a label, without the
+    /// enclosing quotes.
+    pub fn to_record(&self) -> String {
+        if self.flipped {
+            format!("{{{}}}", self.fields_to_record())
+        } else {
+            self.fields_to_record()
         }
     }
 
-    fn id_name<'a>(n: &Node) -> Id<'a> {
-        Id::new(format!("N{}", *n)).unwrap()
+    /// Converts the record into a `LabelText::EscStr`.
+    pub fn into_label(self) -> LabelText<'static> {
+        EscStr(self.to_record().into())
     }
+}
 
-    impl<'a> Labeller<'a, Node, &'a Edge> for LabelledGraph {
-        fn graph_id(&'a self) -> Id<'a> {
-            Id::new(&self.name[..]).unwrap()
-        }
-        fn node_id(&'a self, n: &Node) -> Id<'a> {
-            id_name(n)
-        }
-        fn node_label(&'a self, n: &Node) -> LabelText<'a> {
-            match self.node_labels[*n] {
-                Some(ref l) => LabelStr((*l).into()),
-                None => LabelStr(id_name(n).name()),
-            }
-        }
-        fn edge_label(&'a self, e: &&'a Edge) -> LabelText<'a> {
-            LabelStr(e.label.into())
-        }
-        fn node_style(&'a self, n: &Node) -> Style {
-            self.node_styles[*n]
-        }
-        fn edge_style(&'a self, e: &&'a Edge) -> Style {
-            e.style
-        }
-        fn edge_color(&'a self, e: &&'a Edge) -> Option<LabelText<'a>>
-        {
-            match e.color {
-                Some(l) => {
-                    Some(LabelStr((*l).into()))
-                },
-                None => None,
-            }
-        }
-        fn edge_end_arrow(&'a self, e: &&'a Edge) -> Arrow {
-            e.end_arrow.clone()
-        }
+/// The ends of an edge at which arrowheads are drawn.
+/// See https://graphviz.org/docs/attrs/dir/ for descriptions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Dir {
+    Forward,
+    Back,
+    Both,
+    None_,
+}
 
-        fn edge_start_arrow(&'a self, e: &&'a Edge) -> Arrow {
-            e.start_arrow.clone()
+impl Dir {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            Dir::Forward => "forward",
+            Dir::Back => "back",
+            Dir::Both => "both",
+            Dir::None_ => "none",
         }
     }
+}
 
-    impl<'a> Labeller<'a, Node, &'a Edge> for LabelledGraphWithEscStrs {
-        fn graph_id(&'a self) -> Id<'a> {
-            self.graph.graph_id()
-        }
-        fn node_id(&'a self, n: &Node) -> Id<'a> {
-            self.graph.node_id(n)
-        }
-        fn node_label(&'a self, n: &Node) -> LabelText<'a> {
-            match self.graph.node_label(n) {
-                LabelStr(s) | EscStr(s) | HtmlStr(s) => EscStr(s),
-            }
-        }
-        fn node_color(&'a self, n: &Node) -> Option<LabelText<'a>> {
-            match self.graph.node_color(n) {
-                Some(LabelStr(s)) | Some(EscStr(s)) | Some(HtmlStr(s)) => Some(EscStr(s)),
-                None => None,
-            }
-        }
-        fn edge_label(&'a self, e: &&'a Edge) -> LabelText<'a> {
-            match self.graph.edge_label(e) {
-                LabelStr(s) | EscStr(s) | HtmlStr(s) => EscStr(s),
-            }
-        }
-        fn edge_color(&'a self, e: &&'a Edge) -> Option<LabelText<'a>> {
-            match self.graph.edge_color(e) {
-                Some(LabelStr(s)) | Some(EscStr(s)) | Some(HtmlStr(s)) => Some(EscStr(s)),
-                None => None,
-            }
-        }
+/// This structure holds all information that can describe an arrow connected to
+/// either start or end of an edge.
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct Arrow {
+    pub arrows: Vec<ArrowShape>,
+}
+
+use self::ArrowShape::*;
+
+impl Arrow {
+    /// Return `true` if this is a default arrow.
+    fn is_default(&self) -> bool {
+        self.arrows.is_empty()
     }
 
-    impl<'a> GraphWalk<'a, Node, &'a Edge> for LabelledGraph {
-        fn nodes(&'a self) -> Nodes<'a, Node> {
-            (0..self.node_labels.len()).collect()
+    /// Arrow constructor which returns an empty arrow. Unlike
+    /// `Arrow::default()`, which leaves the arrow to Graphviz and emits
+    /// nothing, this is rendered explicitly as `none`, e.g. to draw a
+    /// plain line for a single edge of a digraph.
+    pub fn none() -> Arrow {
+        Arrow {
+            arrows: vec![NoArrow],
         }
-        fn edges(&'a self) -> Edges<'a, &'a Edge> {
-            self.edges.iter().collect()
+    }
+
+    /// Arrow constructor which returns a regular triangle arrow, without modifiers
+    pub fn normal() -> Arrow {
+        Arrow {
+            arrows: vec![ArrowShape::normal()]
         }
-        fn source(&'a self, edge: &&'a Edge) -> Node {
-            edge.from
+    }
+
+    /// Arrow constructor which returns an arrow created by a given ArrowShape.
+    pub fn from_arrow(arrow: ArrowShape) -> Arrow {
+        Arrow {
+            arrows: vec![arrow],
         }
-        fn target(&'a self, edge: &&'a Edge) -> Node {
-            edge.to
+    }
+
+    /// Clips every shape of this arrow on the other side; see
+    /// `ArrowShape::flip_side`.
+    pub fn flip_sides(self) -> Arrow {
+        Arrow {
+            arrows: self.arrows.into_iter().map(ArrowShape::flip_side).collect(),
         }
     }
 
-    impl<'a> GraphWalk<'a, Node, &'a Edge> for LabelledGraphWithEscStrs {
-        fn nodes(&'a self) -> Nodes<'a, Node> {
-            self.graph.nodes()
+    /// Function which converts given arrow into a renderable form.
+    pub fn to_dot_string(&self) -> String {
+        let mut cow = String::new();
+        for arrow in &self.arrows {
+            cow.push_str(&arrow.to_dot_string());
+        };
+        cow
+    }
+}
+
+
+impl Default for Arrow {
+    /// Arrow constructor which returns a default arrow. No attribute is
+    /// emitted for it, so Graphviz picks the arrow for the graph kind.
+    fn default() -> Arrow {
+        Arrow {
+            arrows: vec![],
         }
-        fn edges(&'a self) -> Edges<'a, &'a Edge> {
-            self.graph.edges()
+    }
+}
+
+impl From<[ArrowShape; 2]> for Arrow {
+    fn from(shapes: [ArrowShape; 2]) -> Arrow {
+        Arrow {
+            arrows: vec![shapes[0], shapes[1]],
         }
-        fn source(&'a self, edge: &&'a Edge) -> Node {
-            edge.from
+    }
+}
+impl From<[ArrowShape; 3]> for Arrow {
+    fn from(shapes: [ArrowShape; 3]) -> Arrow {
+        Arrow {
+            arrows: vec![shapes[0], shapes[1], shapes[2]],
         }
-        fn target(&'a self, edge: &&'a Edge) -> Node {
-            edge.to
+    }
+}
+impl From<[ArrowShape; 4]> for Arrow {
+    fn from(shapes: [ArrowShape; 4]) -> Arrow {
+        Arrow {
+            arrows: vec![shapes[0], shapes[1], shapes[2], shapes[3]],
         }
     }
+}
 
-    fn test_input(g: LabelledGraph) -> io::Result<String> {
-        let mut writer = Vec::new();
-        render(&g, &mut writer).unwrap();
-        let mut s = String::new();
-        Read::read_to_string(&mut &*writer, &mut s)?;
-        Ok(s)
+impl From<ArrowShape> for Arrow {
+    fn from(shape: ArrowShape) -> Arrow {
+        Arrow::from_arrow(shape)
     }
+}
 
-    // All of the tests use raw-strings as the format for the expected outputs,
-    // so that you can cut-and-paste the content into a .dot file yourself to
-    // see what the graphviz visualizer would produce.
+impl From<Vec<ArrowShape>> for Arrow {
+    fn from(arrows: Vec<ArrowShape>) -> Arrow {
+        Arrow { arrows }
+    }
+}
 
-    #[test]
-    fn empty_graph() {
-        let labels: Trivial = UnlabelledNodes(0);
-        let r = test_input(LabelledGraph::new("empty_graph", labels, vec![], None));
-        assert_eq!(r.unwrap(),
-r#"digraph empty_graph {
+/// Arrow modifier that determines if the shape is empty or filled.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Fill {
+    Open,
+    Filled,
 }
-"#);
+
+impl Fill {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            Fill::Open => "o",
+            Fill::Filled => "",
+        }
     }
+}
 
-    #[test]
+/// Arrow modifier that determines if the shape is clipped.
+/// For example `Side::Left` means only left side is visible.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Side {
+    Left,
+    Right,
+    Both,
+}
+
+impl Side {
+    pub fn as_slice(self) -> &'static str {
+        match self {
+            Side::Left  => "l",
+            Side::Right => "r",
+            Side::Both  => "",
+        }
+    }
+
+    /// Swaps `Left` and `Right`; `Both` is left unchanged.
+    pub fn flip(self) -> Side {
+        match self {
+            Side::Left  => Side::Right,
+            Side::Right => Side::Left,
+            Side::Both  => Side::Both,
+        }
+    }
+}
+
+
+/// This enumeration represents all possible arrow edge
+/// as defined in [graphviz documentation](https://graphviz.org/doc/info/arrows.html).
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArrowShape {
+    /// No arrow will be displayed
+    NoArrow,
+    /// Arrow that ends in a triangle. Basically a normal arrow.
+    /// NOTE: there is error in official documentation, this supports both fill and side clipping
+    Normal(Fill, Side),
+    /// Arrow ending in a small square box
+    Box(Fill, Side),
+    /// Arrow ending in a three branching lines also called crow's foot
+    Crow(Side),
+    /// Arrow ending in a curve
+    Curve(Side),
+    /// Arrow ending in an inverted curve
+    ICurve(Fill, Side),
+    /// Arrow ending in an diamond shaped rectangular shape.
+    Diamond(Fill, Side),
+    /// Arrow ending in a circle.
+    Dot(Fill, Side),
+    /// Arrow ending in an inverted triangle.
+    Inv(Fill, Side),
+    /// Arrow ending with a T shaped arrow.
+    Tee(Side),
+    /// Arrow ending with a V shaped arrow.
+    Vee(Side),
+}
+impl ArrowShape {
+    /// Constructor which returns no arrow.
+    pub fn none() -> ArrowShape {
+        ArrowShape::NoArrow
+    }
+
+    /// Constructor which returns normal arrow.
+    pub fn normal() -> ArrowShape {
+        ArrowShape::Normal(Fill::Filled, Side::Both)
+    }
+
+    /// Constructor which returns a regular box arrow.
+    pub fn boxed() -> ArrowShape {
+        ArrowShape::Box(Fill::Filled, Side::Both)
+    }
+
+    /// Constructor which returns a regular crow arrow.
+    pub fn crow() -> ArrowShape {
+        ArrowShape::Crow(Side::Both)
+    }
+
+    /// Constructor which returns a regular curve arrow.
+    pub fn curve() -> ArrowShape {
+        ArrowShape::Curve(Side::Both)
+    }
+
+    /// Constructor which returns an inverted curve arrow.
+    pub fn icurve() -> ArrowShape {
+        ArrowShape::ICurve(Fill::Filled, Side::Both)
+    }
+
+    /// Constructor which returns a diamond arrow.
+    pub fn diamond() -> ArrowShape {
+        ArrowShape::Diamond(Fill::Filled, Side::Both)
+    }
+
+    /// Constructor which returns a circle shaped arrow.
+    pub fn dot() -> ArrowShape {
+        ArrowShape::Dot(Fill::Filled, Side::Both)
+    }
+
+    /// Constructor which returns an inverted triangle arrow.
+    pub fn inv() -> ArrowShape {
+        ArrowShape::Inv(Fill::Filled, Side::Both)
+    }
+
+    /// Constructor which returns a T shaped arrow.
+    pub fn tee() -> ArrowShape {
+        ArrowShape::Tee(Side::Both)
+    }
+
+    /// Constructor which returns a V shaped arrow.
+    pub fn vee() -> ArrowShape {
+        ArrowShape::Vee(Side::Both)
+    }
+
+    /// Clips this arrow on the other side, e.g. to keep its look when
+    /// the `rankdir` of the graph is changed.
+    pub fn flip_side(self) -> ArrowShape {
+        match self {
+            NoArrow             => NoArrow,
+            Normal(fill, side)  => Normal(fill, side.flip()),
+            Box(fill, side)     => Box(fill, side.flip()),
+            Crow(side)          => Crow(side.flip()),
+            Curve(side)         => Curve(side.flip()),
+            ICurve(fill, side)  => ICurve(fill, side.flip()),
+            Diamond(fill, side) => Diamond(fill, side.flip()),
+            Dot(fill, side)     => Dot(fill, side.flip()),
+            Inv(fill, side)     => Inv(fill, side.flip()),
+            Tee(side)           => Tee(side.flip()),
+            Vee(side)           => Vee(side.flip()),
+        }
+    }
+
+    /// Function which renders given ArrowShape into a String for displaying.
+    pub fn to_dot_string(&self) -> String {
+        let mut res = String::new();
+        match *self {
+            Box(fill, side) | ICurve(fill, side)| Diamond(fill, side) |
+            Dot(fill, side) | Inv(fill, side) | Normal(fill, side)=> {
+                res.push_str(fill.as_slice());
+                match side {
+                    Side::Left | Side::Right => res.push_str(side.as_slice()),
+                    Side::Both => {},
+                };
+            },
+            Crow(side) | Curve(side) | Tee(side)
+            | Vee(side) => {
+                match side {
+                    Side::Left | Side::Right => res.push_str(side.as_slice()),
+                    Side::Both => {},
+                }
+            }
+            NoArrow => {},
+        };
+        match *self {
+            NoArrow         => res.push_str("none"),
+            Normal(_, _)    => res.push_str("normal"),
+            Box(_, _)       => res.push_str("box"),
+            Crow(_)         => res.push_str("crow"),
+            Curve(_)        => res.push_str("curve"),
+            ICurve(_, _)    => res.push_str("icurve"),
+            Diamond(_, _)   => res.push_str("diamond"),
+            Dot(_, _)       => res.push_str("dot"),
+            Inv(_, _)       => res.push_str("inv"),
+            Tee(_)          => res.push_str("tee"),
+            Vee(_)          => res.push_str("vee"),
+        };
+        res
+    }
+}
+
+pub type Nodes<'a,N> = Cow<'a,[N]>;
+pub type Edges<'a,E> = Cow<'a,[E]>;
+
+/// Graph kind determines if `digraph` or `graph` is used as keyword
+/// for the graph.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Kind {
+    Digraph,
+    Graph,
+}
+
+impl Kind {
+    /// The keyword to use to introduce the graph.
+    /// Determines which edge syntax must be used, and default style.
+    fn keyword(&self) -> &'static str {
+        match *self {
+            Kind::Digraph => "digraph",
+            Kind::Graph => "graph"
+        }
+    }
+
+    /// The edgeop syntax to use for this graph kind.
+    fn edgeop(&self) -> &'static str {
+        match *self {
+            Kind::Digraph => "->",
+            Kind::Graph => "--",
+        }
+    }
+}
+
+// (The type parameters in GraphWalk should be associated items,
+// when/if Rust supports such.)
+
+/// GraphWalk is an abstraction over a graph = (nodes,edges)
+/// made up of node handles `N` and edge handles `E`, where each `E`
+/// can be mapped to its source and target nodes.
+///
+/// The lifetime parameter `'a` is exposed in this trait (rather than
+/// introduced as a generic parameter on each method declaration) so
+/// that a client impl can choose `N` and `E` that have substructure
+/// that is bound by the self lifetime `'a`.
+///
+/// The `nodes` and `edges` method each return instantiations of
+/// `Cow<[T]>` to leave implementers the freedom to create
+/// entirely new vectors or to pass back slices into internally owned
+/// vectors.
+pub trait GraphWalk<'a, N: Clone, E: Clone> {
+    /// Returns all the nodes in this graph. A graph whose node set is
+    /// implied by its edges can return `nodes_from_edges(self)`.
+    fn nodes(&'a self) -> Nodes<'a, N>;
+    /// Returns all of the edges in this graph.
+    fn edges(&'a self) -> Edges<'a, E>;
+    /// The source node for `edge`.
+    fn source(&'a self, edge: &E) -> N;
+    /// The target node for `edge`.
+    fn target(&'a self, edge: &E) -> N;
+
+    /// A hint for the number of nodes in this graph, used to reserve
+    /// buffer space when rendering into memory. Defaults to `None`.
+    fn num_nodes(&'a self) -> Option<usize> {
+        None
+    }
+
+    /// A hint for the number of edges in this graph, used to reserve
+    /// buffer space when rendering into memory. Defaults to `None`.
+    fn num_edges(&'a self) -> Option<usize> {
+        None
+    }
+}
+
+/// Collects the sources and targets of the edges of `g` into a sorted
+/// list without duplicates, for graphs that have no separate node set.
+/// Nodes without any edges are not found this way.
+///
+/// This is a function rather than a default for `GraphWalk::nodes` as
+/// it needs `N: Ord`, which `GraphWalk` does not require. A graph that
+/// only knows its edges can implement `EdgeListGraph` instead and be
+/// rendered through `edge_list`, which uses this function.
+pub fn nodes_from_edges<'a, N, E, G>(g: &'a G) -> Nodes<'a, N>
+    where N: Clone + Ord + 'a,
+          E: Clone + 'a,
+          G: GraphWalk<'a, N, E> + ?Sized
+{
+    let edges = g.edges();
+    let mut nodes = Vec::with_capacity(edges.len() * 2);
+    for e in edges.iter() {
+        nodes.push(g.source(e));
+        nodes.push(g.target(e));
+    }
+    nodes.sort();
+    nodes.dedup();
+    Cow::Owned(nodes)
+}
+
+/// Generates the `Labeller` methods of a type that hands every call on
+/// to other labellers, so that a new method is forwarded everywhere by
+/// adding it here.
+///
+/// `forward_labeller!(deref)` forwards every method to `**self` and
+/// `forward_labeller!(to field)` to `self.field`.
+/// `forward_labeller!(union)` sends graph-level methods to `self.first`
+/// and node and edge methods to whichever of `self.first` and
+/// `self.second` the item came from. It leaves out the methods returning
+/// identifiers, which `Union` has to prefix itself.
+macro_rules! forward_labeller {
+    (deref) => {
+        forward_labeller!(@methods deref, N, E);
+    };
+    (to $field:ident) => {
+        forward_labeller!(@methods (to $field), N, E);
+    };
+    (union) => {
+        forward_labeller!(@methods union, Either<N1, N2>, Either<E1, E2>);
+    };
+    (@methods $via:tt, $n:ty, $e:ty) => {
+        forward_labeller!(@graph $via fn graph_id(&'a self) -> Id<'a>);
+        forward_labeller!(@graph $via fn graph_attrs(&'a self) -> HashMap<Cow<'a, str>, Cow<'a, str>>);
+        forward_labeller!(@id $via fn subgraph_attrs(&'a self, id: &Id) -> HashMap<Cow<'a, str>, Cow<'a, str>>);
+        forward_labeller!(@graph $via fn trailing_statements(&'a self) -> Vec<Cow<'a, str>>);
+        forward_labeller!(@id $via fn node_id(&'a self, n: &$n) -> Id<'a>);
+        forward_labeller!(@id $via fn try_node_id(&'a self, n: &$n) -> Result<Id<'a>, ()>);
+        forward_labeller!(@item $via fn node_shape(&'a self, n: &$n) -> Option<LabelText<'a>>);
+        forward_labeller!(@item $via fn node_shape_typed(&'a self, n: &$n) -> Option<Shape>);
+        forward_labeller!(@item $via fn node_label(&'a self, n: &$n) -> LabelText<'a>);
+        forward_labeller!(@item $via fn edge_label(&'a self, e: &$e) -> LabelText<'a>);
+        forward_labeller!(@item $via fn node_style(&'a self, n: &$n) -> Style);
+        forward_labeller!(@graph $via fn rank_dir(&'a self) -> Option<RankDir>);
+        forward_labeller!(@graph $via fn splines(&'a self) -> Option<Splines>);
+        forward_labeller!(@graph $via fn concentrate(&self) -> bool);
+        forward_labeller!(@graph $via fn newrank(&self) -> bool);
+        forward_labeller!(@graph $via fn ordering(&self) -> Option<Ordering>);
+        forward_labeller!(@graph $via fn bgcolor(&'a self) -> Option<LabelText<'a>>);
+        forward_labeller!(@graph $via fn graph_label(&'a self) -> Option<LabelText<'a>>);
+        forward_labeller!(@graph $via fn label_loc(&'a self) -> Option<LabelLoc>);
+        forward_labeller!(@graph $via fn label_just(&'a self) -> Option<LabelJust>);
+        forward_labeller!(@graph $via fn pack(&self) -> Option<Pack>);
+        forward_labeller!(@graph $via fn packmode(&self) -> Option<PackMode>);
+        forward_labeller!(@graph $via fn size(&'a self) -> Option<(f64, f64)>);
+        forward_labeller!(@graph $via fn ratio(&self) -> Option<Ratio>);
+        forward_labeller!(@graph $via fn margin(&'a self) -> Option<(f64, f64)>);
+        forward_labeller!(@graph $via fn rotate(&self) -> Option<u32>);
+        forward_labeller!(@graph $via fn graph_font(&'a self) -> Option<Font<'a>>);
+        forward_labeller!(@graph $via fn sep(&self) -> Option<Sep>);
+        forward_labeller!(@graph $via fn esep(&self) -> Option<Sep>);
+        forward_labeller!(@graph $via fn overlap(&self) -> Option<Overlap>);
+        forward_labeller!(@graph $via fn dpi(&self) -> Option<f64>);
+        forward_labeller!(@graph $via fn viewport(&'a self) -> Option<Cow<'a, str>>);
+        forward_labeller!(@graph $via fn layers(&'a self) -> Option<Vec<Cow<'a, str>>>);
+        forward_labeller!(@item $via fn node_color(&'a self, n: &$n) -> Option<LabelText<'a>>);
+        forward_labeller!(@item $via fn node_pen(&'a self, n: &$n) -> Option<Pen<'a>>);
+        forward_labeller!(@item $via fn node_fontcolor(&'a self, n: &$n) -> Option<LabelText<'a>>);
+        forward_labeller!(@item $via fn node_image(&'a self, n: &$n) -> Option<Cow<'a, str>>);
+        forward_labeller!(@item $via fn node_imagepos(&'a self, n: &$n) -> Option<ImagePos>);
+        forward_labeller!(@item $via fn node_size(&'a self, n: &$n) -> Option<NodeSize>);
+        forward_labeller!(@item $via fn node_margin(&'a self, n: &$n) -> Option<(f64, f64)>);
+        forward_labeller!(@item $via fn node_svg_id(&'a self, n: &$n) -> Option<Id<'a>>);
+        forward_labeller!(@item $via fn node_comment(&'a self, n: &$n) -> Option<Cow<'a, str>>);
+        forward_labeller!(@item $via fn node_attr_comment(&'a self, n: &$n) -> Option<Cow<'a, str>>);
+        forward_labeller!(@id $via fn node_group(&'a self, n: &$n) -> Option<Id<'a>>);
+        forward_labeller!(@id $via fn node_subgraph(&'a self, n: &$n) -> Option<Id<'a>>);
+        forward_labeller!(@item $via fn node_nojustify(&'a self, n: &$n) -> bool);
+        forward_labeller!(@item $via fn node_layer(&'a self, n: &$n) -> Option<Cow<'a, str>>);
+        forward_labeller!(@item $via fn node_pos(&'a self, n: &$n) -> Option<Cow<'a, str>>);
+        forward_labeller!(@item $via fn node_gradientangle(&'a self, n: &$n) -> Option<f64>);
+        forward_labeller!(@item $via fn node_attrs(&'a self, n: &$n) -> HashMap<Cow<'a, str>, Cow<'a, str>>);
+        forward_labeller!(@item $via fn edge_end_arrow(&'a self, e: &$e) -> Arrow);
+        forward_labeller!(@item $via fn edge_start_arrow(&'a self, e: &$e) -> Arrow);
+        forward_labeller!(@item $via fn edge_dir(&'a self, e: &$e) -> Option<Dir>);
+        forward_labeller!(@item $via fn edge_arrowsize(&'a self, e: &$e) -> Option<f64>);
+        forward_labeller!(@item $via fn edge_labeldistance(&'a self, e: &$e) -> Option<f64>);
+        forward_labeller!(@item $via fn edge_labelangle(&'a self, e: &$e) -> Option<f64>);
+        forward_labeller!(@item $via fn edge_decorate(&'a self, e: &$e) -> Option<bool>);
+        forward_labeller!(@item $via fn edge_labelfloat(&'a self, e: &$e) -> Option<bool>);
+        forward_labeller!(@item $via fn edge_style(&'a self, e: &$e) -> Style);
+        forward_labeller!(@item $via fn edge_color(&'a self, e: &$e) -> Option<LabelText<'a>>);
+        forward_labeller!(@item $via fn edge_colors(&'a self, e: &$e) -> Vec<LabelText<'a>>);
+        forward_labeller!(@item $via fn edge_pen(&'a self, e: &$e) -> Option<Pen<'a>>);
+        forward_labeller!(@item $via fn edge_fontcolor(&'a self, e: &$e) -> Option<LabelText<'a>>);
+        forward_labeller!(@item $via fn edge_constraint(&'a self, e: &$e) -> Option<bool>);
+        forward_labeller!(@item $via fn edge_svg_id(&'a self, e: &$e) -> Option<Id<'a>>);
+        forward_labeller!(@id $via fn edge_samehead(&'a self, e: &$e) -> Option<Id<'a>>);
+        forward_labeller!(@id $via fn edge_sametail(&'a self, e: &$e) -> Option<Id<'a>>);
+        forward_labeller!(@item $via fn edge_comment(&'a self, e: &$e) -> Option<Cow<'a, str>>);
+        forward_labeller!(@item $via fn edge_attr_comment(&'a self, e: &$e) -> Option<Cow<'a, str>>);
+        forward_labeller!(@item $via fn edge_nojustify(&'a self, e: &$e) -> bool);
+        forward_labeller!(@item $via fn edge_layer(&'a self, e: &$e) -> Option<Cow<'a, str>>);
+        forward_labeller!(@item $via fn edge_pos(&'a self, e: &$e) -> Option<Cow<'a, str>>);
+        forward_labeller!(@item $via fn edge_attrs(&'a self, e: &$e) -> HashMap<Cow<'a, str>, Cow<'a, str>>);
+        forward_labeller!(@graph $via fn kind(&self) -> Kind);
+    };
+    (@$kind:ident deref
+     fn $name:ident(&$($lt:lifetime)? self $(, $arg:ident: $ty:ty)*) -> $ret:ty) => {
+        fn $name(&$($lt)? self $(, $arg: $ty)*) -> $ret {
+            (**self).$name($($arg),*)
+        }
+    };
+    (@$kind:ident (to $field:ident)
+     fn $name:ident(&$($lt:lifetime)? self $(, $arg:ident: $ty:ty)*) -> $ret:ty) => {
+        fn $name(&$($lt)? self $(, $arg: $ty)*) -> $ret {
+            self.$field.$name($($arg),*)
+        }
+    };
+    (@graph union fn $name:ident(&$($lt:lifetime)? self) -> $ret:ty) => {
+        fn $name(&$($lt)? self) -> $ret {
+            self.first.$name()
+        }
+    };
+    (@item union fn $name:ident(&$lt:lifetime self, $arg:ident: $ty:ty) -> $ret:ty) => {
+        fn $name(&$lt self, $arg: $ty) -> $ret {
+            match *$arg {
+                Either::Left(ref $arg) => self.first.$name($arg),
+                Either::Right(ref $arg) => self.second.$name($arg),
+            }
+        }
+    };
+    (@id union $($method:tt)*) => {};
+}
+
+/// Forwards every method to the referenced graph, so that a `&G` can be
+/// passed wherever a `Labeller` is expected.
+impl<'a, N, E, G: Labeller<'a, N, E>> Labeller<'a, N, E> for &'a G {
+    forward_labeller!(deref);
+}
+
+/// Forwards every method to the referenced graph, like the `Labeller`
+/// impl for `&G`.
+impl<'a, N: Clone, E: Clone, G: GraphWalk<'a, N, E>> GraphWalk<'a, N, E> for &'a G {
+    fn nodes(&'a self) -> Nodes<'a, N> {
+        (**self).nodes()
+    }
+
+    fn edges(&'a self) -> Edges<'a, E> {
+        (**self).edges()
+    }
+
+    fn source(&'a self, edge: &E) -> N {
+        (**self).source(edge)
+    }
+
+    fn target(&'a self, edge: &E) -> N {
+        (**self).target(edge)
+    }
+
+    fn num_nodes(&'a self) -> Option<usize> {
+        (**self).num_nodes()
+    }
+
+    fn num_edges(&'a self) -> Option<usize> {
+        (**self).num_edges()
+    }
+}
+
+/// A graph described by its edges alone, whose nodes are the sources
+/// and targets of those edges. See `edge_list`.
+pub trait EdgeListGraph<'a, N: Clone, E: Clone> {
+    /// Returns all of the edges in this graph.
+    fn edges(&'a self) -> Edges<'a, E>;
+    /// The source node for `edge`.
+    fn source(&'a self, edge: &E) -> N;
+    /// The target node for `edge`.
+    fn target(&'a self, edge: &E) -> N;
+}
+
+/// An `EdgeListGraph` that implements `GraphWalk`. See `edge_list`.
+pub struct EdgeList<'a, G: 'a> {
+    graph: &'a G,
+}
+
+/// Wraps `g` into a graph implementing `GraphWalk`, whose nodes are
+/// collected from the edges of `g` by `nodes_from_edges`. Every
+/// `Labeller` method is forwarded to `g`.
+///
+/// `GraphWalk` cannot provide this as a default for graphs that
+/// implement `EdgeListGraph` themselves, as such a blanket impl would
+/// overlap with the impl for `&G`.
+pub fn edge_list<'a, G>(g: &'a G) -> EdgeList<'a, G> {
+    EdgeList { graph: g }
+}
+
+impl<'a, N, E, G: Labeller<'a, N, E>> Labeller<'a, N, E> for EdgeList<'a, G> {
+    forward_labeller!(to graph);
+}
+
+impl<'a, N, E, G> GraphWalk<'a, N, E> for EdgeList<'a, G>
+    where N: Clone + Ord + 'a,
+          E: Clone + 'a,
+          G: EdgeListGraph<'a, N, E>
+{
+    fn nodes(&'a self) -> Nodes<'a, N> {
+        nodes_from_edges(self)
+    }
+
+    fn edges(&'a self) -> Edges<'a, E> {
+        self.graph.edges()
+    }
+
+    fn source(&'a self, edge: &E) -> N {
+        self.graph.source(edge)
+    }
+
+    fn target(&'a self, edge: &E) -> N {
+        self.graph.target(edge)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenderOption {
+    NoEdgeLabels,
+    NoNodeLabels,
+    NoEdgeStyles,
+    NoEdgeColors,
+    NoNodeStyles,
+    NoNodeColors,
+    /// Omits custom arrows, and draws the edges of a digraph without
+    /// any arrowheads at all (`dir=none`).
+    NoArrows,
+    /// Emits the nodes ordered by their identifier rather than in the
+    /// order returned by `GraphWalk::nodes`.
+    SortNodes,
+    /// Emits the edges ordered by their source and target identifiers,
+    /// and parallel edges by their rendered statements, rather than in
+    /// the order returned by `GraphWalk::edges`.
+    SortEdges,
+    /// Emits only the first of the edges that have the same source and
+    /// target identifiers. The labels, colors and other attributes of
+    /// the dropped edges are lost.
+    MergeParallelEdges,
+    /// Implies `SortNodes` and `SortEdges`, and additionally writes the
+    /// attributes of every node and edge sorted by name, one per `[...]`,
+    /// so that the output of equal graphs is byte-identical and diffs
+    /// line by line.
+    Canonical,
+    /// Writes all attributes of a node or edge in a single `[...]`,
+    /// separated by commas, e.g. `[label="a", color="red"]`, rather than
+    /// one `[...]` per attribute.
+    MergeAttributes,
+}
+
+/// The kind of statement passed to the hook of `render_with_hook`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StatementKind {
+    /// A graph attribute, such as `rankdir` or an entry of
+    /// `Labeller::graph_attrs`.
+    GraphAttr,
+    /// A node statement.
+    Node,
+    /// An edge statement.
+    Edge,
+}
+
+/// Returns vec holding all the default render options.
+pub fn default_options() -> Vec<RenderOption> {
+    vec![]
+}
+
+/// Renders graph `g` into the writer `w` in DOT syntax.
+/// (Simple wrapper around `render_opts` that passes a default set of options.)
+pub fn render<'a,
+              N: Clone + 'a,
+              E: Clone + 'a,
+              G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+              W: Write>
+    (g: &'a G,
+     w: &mut W)
+     -> io::Result<()> {
+    render_opts(g, w, &[])
+}
+
+/// Renders graph `g` into the writer `w` in DOT syntax.
+/// (Main entry point for the library.)
+///
+/// Numeric attributes that are NaN or infinite are left out, as DOT
+/// cannot express them.
+pub fn render_opts<'a,
+                   N: Clone + 'a,
+                   E: Clone + 'a,
+                   G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                   W: Write>
+    (g: &'a G,
+     w: &mut W,
+     options: &[RenderOption])
+     -> io::Result<()> {
+    render_impl(g, w, g.kind().keyword(), g.kind(), options, |n| Ok(g.node_id(n)), |_, _| {})
+}
+
+/// Renders every graph of `graphs` into the writer `w` as a complete
+/// DOT graph, each with its own id and kind, separated by blank lines.
+/// Rendering stops at the first graph that fails.
+pub fn render_all<'a,
+                  N: Clone + 'a,
+                  E: Clone + 'a,
+                  G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                  W: Write>
+    (graphs: &'a [G],
+     w: &mut W,
+     options: &[RenderOption])
+     -> io::Result<()> {
+    for (i, g) in graphs.iter().enumerate() {
+        if i > 0 {
+            writeln(w, &[])?;
+        }
+        render_opts(g, w, options)?;
+    }
+    Ok(())
+}
+
+/// Renders graph `g` into the writer `w` in DOT syntax, like
+/// `render_opts`, but passes every statement to `hook` before it is
+/// written. The statement is handed over without its indentation and
+/// terminating `;`, and may be rewritten in place, e.g. to append
+/// attributes this crate does not model.
+pub fn render_with_hook<'a,
+                        N: Clone + 'a,
+                        E: Clone + 'a,
+                        G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                        W: Write,
+                        H: FnMut(StatementKind, &mut String)>
+    (g: &'a G,
+     w: &mut W,
+     options: &[RenderOption],
+     hook: H)
+     -> io::Result<()> {
+    render_impl(g, w, g.kind().keyword(), g.kind(), options, |n| Ok(g.node_id(n)), hook)
+}
+
+/// Renders graph `g` into the writer `w` as a bare `subgraph` block,
+/// to be pasted into a larger DOT document. The edges are written
+/// with the edgeop of `kind`, which must be the kind of the enclosing
+/// graph; `Labeller::kind` is not used.
+pub fn render_subgraph<'a,
+                       N: Clone + 'a,
+                       E: Clone + 'a,
+                       G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                       W: Write>
+    (g: &'a G,
+     w: &mut W,
+     kind: Kind,
+     options: &[RenderOption])
+     -> io::Result<()> {
+    render_impl(g, w, "subgraph", kind, options, |n| Ok(g.node_id(n)), |_, _| {})
+}
+
+/// Renders graph `g` into a `String` in DOT syntax.
+/// (Simple wrapper around `render_opts_to_string` that passes a default set of options.)
+pub fn render_to_string<'a,
+                        N: Clone + 'a,
+                        E: Clone + 'a,
+                        G: Labeller<'a, N, E> + GraphWalk<'a, N, E>>
+    (g: &'a G)
+     -> io::Result<String> {
+    render_opts_to_string(g, &[])
+}
+
+/// Renders graph `g` into a `String` in DOT syntax. The buffer is
+/// sized up front from the `GraphWalk::num_nodes` and
+/// `GraphWalk::num_edges` hints, when given.
+pub fn render_opts_to_string<'a,
+                             N: Clone + 'a,
+                             E: Clone + 'a,
+                             G: Labeller<'a, N, E> + GraphWalk<'a, N, E>>
+    (g: &'a G,
+     options: &[RenderOption])
+     -> io::Result<String> {
+    // Rough sizes of the header and footer, and of a typical node or
+    // edge statement.
+    const GRAPH_BYTES: usize = 32;
+    const NODE_BYTES: usize = 32;
+    const EDGE_BYTES: usize = 48;
+
+    let capacity = GRAPH_BYTES
+        + g.num_nodes().unwrap_or(0) * NODE_BYTES
+        + g.num_edges().unwrap_or(0) * EDGE_BYTES;
+    let mut w = Vec::with_capacity(capacity);
+    render_opts(g, &mut w, options)?;
+    String::from_utf8(w).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Renders graph `g` in DOT syntax into the file at `path`.
+/// (Simple wrapper around `render_opts_to_file` that passes a default set of options.)
+pub fn render_to_file<'a,
+                      N: Clone + 'a,
+                      E: Clone + 'a,
+                      G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                      P: AsRef<Path>>
+    (g: &'a G,
+     path: P)
+     -> io::Result<()> {
+    render_opts_to_file(g, path, &[])
+}
+
+/// Renders graph `g` in DOT syntax into the file at `path`, which is
+/// created, or truncated if it exists. The output is buffered and
+/// flushed before returning.
+pub fn render_opts_to_file<'a,
+                           N: Clone + 'a,
+                           E: Clone + 'a,
+                           G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                           P: AsRef<Path>>
+    (g: &'a G,
+     path: P,
+     options: &[RenderOption])
+     -> io::Result<()> {
+    let mut w = io::BufWriter::new(File::create(path)?);
+    render_opts(g, &mut w, options)?;
+    w.flush()
+}
+
+/// Renders graph `g` in DOT syntax and yields the header, every node
+/// and edge statement, and the footer as separate lines, without their
+/// line terminators. The lines are rendered up front, so an error, e.g.
+/// an unbalanced trailing statement, is returned before any line is
+/// yielded.
+pub fn render_lines<'a,
+                    N: Clone + 'a,
+                    E: Clone + 'a,
+                    G: Labeller<'a, N, E> + GraphWalk<'a, N, E>>
+    (g: &'a G)
+     -> io::Result<impl Iterator<Item = String>> {
+    let text = render_to_string(g)?;
+    Ok(text.lines().map(String::from).collect::<Vec<_>>().into_iter())
+}
+
+/// Renders graph `g` into the writer `w` in DOT syntax, like
+/// `render_opts`, but obtains the node identifiers from
+/// `Labeller::try_node_id`. An invalid identifier is reported as an
+/// `io::Error` of kind `InvalidInput` rather than a panic.
+///
+/// Only the node identifiers are checked: `Labeller::graph_id` is
+/// still called as is, so a graph id that cannot be built, e.g. by
+/// `Id::new(..).unwrap()`, still panics.
+pub fn render_checked<'a,
+                      N: Clone + 'a,
+                      E: Clone + 'a,
+                      G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                      W: Write>
+    (g: &'a G,
+     w: &mut W,
+     options: &[RenderOption])
+     -> io::Result<()> {
+    render_impl(g, w, g.kind().keyword(), g.kind(), options, |n| {
+        g.try_node_id(n).map_err(|()| io::Error::new(io::ErrorKind::InvalidInput, "invalid node id"))
+    }, |_, _| {})
+}
+
+/// Writes the opening line of graph `g` and its graph attributes.
+/// Together with `render_nodes`, `render_edges` and `render_footer`
+/// this writes the same output as `render_opts`, but lets the caller
+/// emit the parts separately and interleave them with other content.
+pub fn render_header<'a,
+                     N: Clone + 'a,
+                     E: Clone + 'a,
+                     G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                     W: Write>
+    (g: &'a G,
+     w: &mut W)
+     -> io::Result<()> {
+    write_header(g, w, g.kind().keyword(), g.kind(), &mut |_, _| {})
+}
+
+/// Writes only the node statements of graph `g`, without the
+/// enclosing braces.
+pub fn render_nodes<'a,
+                    N: Clone + 'a,
+                    E: Clone + 'a,
+                    G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                    W: Write>
+    (g: &'a G,
+     w: &mut W,
+     options: &[RenderOption])
+     -> io::Result<()> {
+    write_nodes(g, w, options, &|n| Ok(g.node_id(n)), &mut |_, _| {})
+}
+
+/// Writes only the edge statements of graph `g`, without the
+/// enclosing braces.
+pub fn render_edges<'a,
+                    N: Clone + 'a,
+                    E: Clone + 'a,
+                    G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                    W: Write>
+    (g: &'a G,
+     w: &mut W,
+     options: &[RenderOption])
+     -> io::Result<()> {
+    write_edges(g, w, g.kind(), options, &|n| Ok(g.node_id(n)), &mut |_, _| {})
+}
+
+/// Writes the trailing statements of graph `g` and the closing brace
+/// of a graph started with `render_header`.
+pub fn render_footer<'a,
+                     N: Clone + 'a,
+                     E: Clone + 'a,
+                     G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                     W: Write>
+    (g: &'a G,
+     w: &mut W)
+     -> io::Result<()> {
+    write_footer(g, w)
+}
+
+/// Checks that `dot` is structurally sound DOT, e.g. before it is
+/// written to a file: every graph starts with `graph` or `digraph`,
+/// optionally preceded by `strict`, braces, attribute lists and quoted
+/// strings are closed, quoted strings contain no unescaped quote, and
+/// every statement of a graph ends with `;` unless it opens a subgraph
+/// or a `{...}` block, whose own statements are not checked. Attribute
+/// lists and quoted strings may span lines. This is a cheap sanity
+/// check rather than a full parser, so it accepts some input that
+/// Graphviz rejects, and rejects statements that Graphviz lets run
+/// together without a `;`.
+pub fn validate(dot: &str) -> Result<(), ValidationError> {
+    let mut chars = Vec::with_capacity(dot.len());
+    let (mut line, mut column) = (1, 1);
+    for c in dot.chars() {
+        chars.push((line, column, c));
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    let end = (line, column, '\n');
+    let error = |(line, column, _): (usize, usize, char), message| {
+        Err(ValidationError { line, column, message })
+    };
+    let word_end = |mut i: usize| {
+        while i < chars.len() && chars[i].2.is_ascii_alphabetic() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut graphs = 0;
+    let mut braces = Vec::new();
+    let mut bracket = None;
+    let mut statement = None;
+    let mut in_header = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].2;
+        let next = chars.get(i + 1).map(|&(_, _, c)| c);
+        if braces.len() == 1 && bracket.is_none() && statement.is_none()
+            && !c.is_whitespace() && !matches!(c, ';' | '{' | '}' | '/') {
+            statement = Some(chars[i]);
+        }
+        match c {
+            '/' if next == Some('/') => {
+                while i < chars.len() && chars[i].2 != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if next == Some('*') => {
+                let start = chars[i];
+                i += 2;
+                while !(chars.get(i).map(|c| c.2) == Some('*') && chars.get(i + 1).map(|c| c.2) == Some('/')) {
+                    if i >= chars.len() {
+                        return error(start, "unterminated comment");
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            c if c.is_whitespace() => {}
+            '{' | '}' if bracket.is_some() => return error(bracket.unwrap(), "unterminated attribute list"),
+            '}' if braces.is_empty() => return error(chars[i], "unmatched `}`"),
+            '}' => {
+                if let (Some(start), 1) = (statement, braces.len()) {
+                    return error(start, "unterminated statement");
+                }
+                braces.pop();
+            }
+            ';' if bracket.is_none() => statement = None,
+            _ if braces.is_empty() && !in_header => {
+                let mut start = i;
+                i = word_end(i);
+                let mut word = chars[start..i].iter().map(|c| c.2).collect::<String>();
+                if word.eq_ignore_ascii_case("strict") {
+                    while i < chars.len() && chars[i].2.is_whitespace() {
+                        i += 1;
+                    }
+                    start = i;
+                    i = word_end(i);
+                    word = chars[start..i].iter().map(|c| c.2).collect();
+                }
+                if !word.eq_ignore_ascii_case("graph") && !word.eq_ignore_ascii_case("digraph") {
+                    return error(chars.get(start).copied().unwrap_or(end), "expected `graph` or `digraph`");
+                }
+                graphs += 1;
+                in_header = true;
+                continue;
+            }
+            '{' => {
+                braces.push(chars[i]);
+                statement = None;
+                in_header = false;
+            }
+            '[' if bracket.is_none() => bracket = Some(chars[i]),
+            ']' if bracket.is_none() => return error(chars[i], "unmatched `]`"),
+            ']' => bracket = None,
+            '"' => {
+                let start = chars[i];
+                i += 1;
+                loop {
+                    match chars.get(i).map(|c| c.2) {
+                        None => return error(start, "unterminated string"),
+                        Some('\\') => i += 2,
+                        Some('"') => break,
+                        Some(_) => i += 1,
+                    }
+                }
+                if let Some(&(_, _, c)) = chars.get(i + 1) {
+                    if c.is_alphanumeric() || c == '_' || c == '"' {
+                        return error(chars[i], "unescaped quote in string");
+                    }
+                }
+            }
+            '<' => {
+                let start = chars[i];
+                let mut depth = 0;
+                loop {
+                    match chars.get(i).map(|c| c.2) {
+                        None => return error(start, "unterminated HTML string"),
+                        Some('<') => depth += 1,
+                        Some('>') => depth -= 1,
+                        Some(_) => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    i += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if let Some(open) = bracket {
+        return error(open, "unterminated attribute list");
+    }
+    if let Some(&open) = braces.last() {
+        return error(open, "unclosed `{`");
+    }
+    if in_header {
+        return error(end, "expected `{`");
+    }
+    if graphs == 0 {
+        return error(end, "expected `graph` or `digraph`");
+    }
+    Ok(())
+}
+
+fn render_impl<'a,
+               N: Clone + 'a,
+               E: Clone + 'a,
+               G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+               W: Write,
+               F: Fn(&N) -> io::Result<Id<'a>>,
+               H: FnMut(StatementKind, &mut String)>
+    (g: &'a G,
+     w: &mut W,
+     keyword: &str,
+     kind: Kind,
+     options: &[RenderOption],
+     node_id: F,
+     mut hook: H)
+     -> io::Result<()> {
+    write_header(g, w, keyword, kind, &mut hook)?;
+    write_nodes(g, w, options, &node_id, &mut hook)?;
+    write_edges(g, w, kind, options, &node_id, &mut hook)?;
+    write_footer(g, w)
+}
+
+fn writeln<W: Write>(w: &mut W, arg: &[&str]) -> io::Result<()> {
+    for &s in arg {
+        w.write_all(s.as_bytes())?;
+    }
+    writeln!(w)
+}
+
+fn indent<W: Write>(w: &mut W, depth: usize) -> io::Result<()> {
+    for _ in 0..depth {
+        w.write_all(b"    ")?;
+    }
+    Ok(())
+}
+
+fn comment<W: Write>(w: &mut W, depth: usize, text: &str) -> io::Result<()> {
+    for line in text.split(['\n', '\r']).filter(|line| !line.is_empty()) {
+        indent(w, depth)?;
+        writeln(w, &["// ", line])?;
+    }
+    Ok(())
+}
+
+fn statement<W: Write, H: FnMut(StatementKind, &mut String)>
+    (w: &mut W, depth: usize, hook: &mut H, kind: StatementKind, arg: &[&str]) -> io::Result<()> {
+    let mut line = arg.concat();
+    hook(kind, &mut line);
+    indent(w, depth)?;
+    writeln(w, &[&line, ";"])
+}
+
+fn write_footer<'a,
+                N: Clone + 'a,
+                E: Clone + 'a,
+                G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                W: Write>
+    (g: &'a G,
+     w: &mut W)
+     -> io::Result<()> {
+    for statement in g.trailing_statements() {
+        if !balanced_braces(&statement) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "unbalanced braces in trailing statement"));
+        }
+        indent(w, 1)?;
+        writeln(w, &[&statement])?;
+    }
+    writeln(w, &["}"])
+}
+
+/// Returns `true` if every `}` in `s` outside of a quoted string, an
+/// HTML string or a comment closes a preceding `{`, and every `{` is
+/// closed.
+fn balanced_braces(s: &str) -> bool {
+    let mut depth = 0usize;
+    // The statement is written indented, so only a `#` after a newline
+    // starts a line, and with it a preprocessor comment.
+    let mut line_start = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '<' => {
+                let mut angles = 1;
+                for c in chars.by_ref() {
+                    match c {
+                        '<' => angles += 1,
+                        '>' if angles == 1 => break,
+                        '>' => angles -= 1,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut star = false;
+                for c in chars.by_ref() {
+                    if star && c == '/' {
+                        break;
+                    }
+                    star = c == '*';
+                }
+            }
+            '#' if line_start => {
+                chars.by_ref().find(|&c| c == '\n');
+                continue;
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+                line_start = true;
+                continue;
+            }
+            '{' => depth += 1,
+            '}' => match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                None => return false,
+            },
+            _ => {}
+        }
+        line_start = c == '\n';
+    }
+    depth == 0
+}
+
+/// Rewrites the attribute lists of the node or edge statement `stmt` as
+/// requested by `options`: with `Canonical` the attributes are sorted by
+/// name, and with `MergeAttributes` they are written in a single `[...]`
+/// separated by commas, else one per `[...]`. Quoted strings and HTML
+/// labels are kept intact.
+///
+/// Attributes are split at the `]` closing each list, and at a space
+/// following a quoted value, which is how the arrow attributes of an
+/// edge share a list. Unquoted values, e.g. from `Labeller::node_attrs`,
+/// are never split.
+fn rewrite_statement(stmt: &str, options: &[RenderOption]) -> String {
+    let mut head = String::new();
+    let mut attrs = Vec::new();
+    let mut current = String::new();
+    let mut in_list = false;
+    let mut angles = 0usize;
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in stmt.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' if angles == 0 => quoted = !quoted,
+            '[' if !in_list && !quoted => {
+                in_list = true;
+                continue;
+            }
+            _ if !in_list => {}
+            '<' if !quoted => angles += 1,
+            '>' if !quoted && angles > 0 => angles -= 1,
+            ']' if !quoted && angles == 0 => {
+                if !current.is_empty() {
+                    attrs.push(std::mem::take(&mut current));
+                }
+                in_list = false;
+                continue;
+            }
+            ' ' if !quoted && angles == 0 && current.ends_with('"') => {
+                attrs.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        if in_list {
+            current.push(c);
+        } else {
+            head.push(c);
+        }
+    }
+    if !current.is_empty() {
+        attrs.push(current);
+    }
+    if options.contains(&RenderOption::Canonical) {
+        attrs.sort_by(|a, b| (a.split('=').next(), a).cmp(&(b.split('=').next(), b)));
+    }
+    if options.contains(&RenderOption::MergeAttributes) {
+        if !attrs.is_empty() {
+            head.push('[');
+            head.push_str(&attrs.join(", "));
+            head.push(']');
+        }
+        return head;
+    }
+    for attr in attrs {
+        head.push('[');
+        head.push_str(&attr);
+        head.push(']');
+    }
+    head
+}
+
+fn write_header<'a,
+                N: Clone + 'a,
+                E: Clone + 'a,
+                G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+                W: Write,
+                H: FnMut(StatementKind, &mut String)>
+    (g: &'a G,
+     w: &mut W,
+     keyword: &str,
+     kind: Kind,
+     hook: &mut H)
+     -> io::Result<()> {
+    writeln(w, &[keyword, " ", &g.graph_id().to_dot_string(), " {"])?;
+    if kind == Kind::Digraph {
+        if let Some(rankdir) = g.rank_dir() {
+            statement(w, 1, hook, StatementKind::GraphAttr, &["rankdir=\"", rankdir.as_slice(), "\""])?;
+        }
+    }
+
+    if let Some(splines) = g.splines() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["splines=\"", splines.as_slice(), "\""])?;
+    }
+
+    if g.concentrate() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["concentrate=true"])?;
+    }
+
+    if g.newrank() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["newrank=true"])?;
+    }
+
+    if let Some(ordering) = g.ordering() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["ordering=\"", ordering.as_slice(), "\""])?;
+    }
+
+    if let Some(bgcolor) = g.bgcolor() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["bgcolor=", &bgcolor.to_dot_string()])?;
+    }
+
+    if let Some(label) = g.graph_label() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["label=", &label.to_dot_string()])?;
+    }
+
+    if let Some(loc) = g.label_loc() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["labelloc=\"", loc.as_slice(), "\""])?;
+    }
+
+    if let Some(just) = g.label_just() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["labeljust=\"", just.as_slice(), "\""])?;
+    }
+
+    if let Some(pack) = g.pack() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["pack=", &pack.to_dot_string()])?;
+    }
+
+    if let Some(packmode) = g.packmode() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["packmode=\"", packmode.as_slice(), "\""])?;
+    }
+
+    if let Some((width, height)) = g.size().filter(|&(w, h)| w.is_finite() && h.is_finite()) {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["size=\"", &width.to_string(), ",", &height.to_string(), "\""])?;
+    }
+
+    if let Some(ratio) = g.ratio().filter(|r| r.is_finite()) {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["ratio=", &ratio.to_dot_string()])?;
+    }
+
+    if let Some((x, y)) = g.margin().filter(|&(x, y)| x.is_finite() && y.is_finite()) {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["margin=\"", &x.to_string(), ",", &y.to_string(), "\""])?;
+    }
+
+    if let Some(rotate) = g.rotate() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["rotate=", &rotate.to_string()])?;
+    }
+
+    if let Some(font) = g.graph_font() {
+        if let Some(name) = font.name {
+            statement(w, 1, hook, StatementKind::GraphAttr, &["fontname=", &LabelText::label(name).to_dot_string()])?;
+        }
+        if let Some(size) = font.size.filter(|s| s.is_finite()) {
+            statement(w, 1, hook, StatementKind::GraphAttr, &["fontsize=", &size.to_string()])?;
+        }
+    }
+
+    if let Some(sep) = g.sep().filter(|s| s.is_finite()) {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["sep=\"", &sep.to_dot_string(), "\""])?;
+    }
+
+    if let Some(esep) = g.esep().filter(|s| s.is_finite()) {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["esep=\"", &esep.to_dot_string(), "\""])?;
+    }
+
+    if let Some(overlap) = g.overlap() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["overlap=", overlap.as_slice()])?;
+    }
+
+    if let Some(dpi) = g.dpi().filter(|d| d.is_finite()) {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["dpi=", &dpi.to_string()])?;
+    }
+
+    if let Some(viewport) = g.viewport() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &["viewport=", &LabelText::label(viewport).to_dot_string()])?;
+    }
+
+    if let Some(layers) = g.layers() {
+        let layers = LabelText::label(layers.join(":")).to_dot_string();
+        statement(w, 1, hook, StatementKind::GraphAttr, &["layers=", &layers])?;
+    }
+
+    let mut attrs = g.graph_attrs().into_iter().collect::<Vec<_>>();
+    attrs.sort();
+    for (name, value) in attrs.iter() {
+        statement(w, 1, hook, StatementKind::GraphAttr, &[name, "=", value])?;
+    }
+    Ok(())
+}
+
+fn write_nodes<'a,
+               N: Clone + 'a,
+               E: Clone + 'a,
+               G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+               W: Write,
+               F: Fn(&N) -> io::Result<Id<'a>>,
+               H: FnMut(StatementKind, &mut String)>
+    (g: &'a G,
+     w: &mut W,
+     options: &[RenderOption],
+     node_id: &F,
+     hook: &mut H)
+     -> io::Result<()> {
+    let mut nodes = g.nodes();
+    if options.contains(&RenderOption::SortNodes) || options.contains(&RenderOption::Canonical) {
+        let mut keyed = nodes.iter()
+            .map(|n| Ok((node_id(n)?.name().into_owned(), n.clone())))
+            .collect::<io::Result<Vec<_>>>()?;
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        nodes = keyed.into_iter().map(|(_, n)| n).collect();
+    }
+
+    let mut loose = Vec::new();
+    let mut subgraphs: Vec<(Id<'a>, Vec<N>)> = Vec::new();
+    for n in nodes.iter() {
+        match g.node_subgraph(n) {
+            None => loose.push(n.clone()),
+            Some(id) => match subgraphs.iter_mut().find(|(s, _)| s.as_slice() == id.as_slice()) {
+                Some((_, members)) => members.push(n.clone()),
+                None => subgraphs.push((id, vec![n.clone()])),
+            },
+        }
+    }
+
+    for (id, members) in subgraphs.iter() {
+        indent(w, 1)?;
+        writeln(w, &["subgraph ", &id.to_dot_string(), " {"])?;
+        let mut attrs = g.subgraph_attrs(id).into_iter().collect::<Vec<_>>();
+        attrs.sort();
+        for (name, value) in attrs {
+            let value = LabelText::label(value).to_dot_string();
+            statement(w, 2, hook, StatementKind::GraphAttr, &[&name, "=", &value])?;
+        }
+        for n in members.iter() {
+            write_node(g, w, 2, n, options, node_id, hook)?;
+        }
+        indent(w, 1)?;
+        writeln(w, &["}"])?;
+    }
+
+    for n in loose.iter() {
+        write_node(g, w, 1, n, options, node_id, hook)?;
+    }
+    Ok(())
+}
+
+fn write_node<'a,
+              N: Clone + 'a,
+              E: Clone + 'a,
+              G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+              W: Write,
+              F: Fn(&N) -> io::Result<Id<'a>>,
+              H: FnMut(StatementKind, &mut String)>
+    (g: &'a G,
+     w: &mut W,
+     depth: usize,
+     n: &N,
+     options: &[RenderOption],
+     node_id: &F,
+     hook: &mut H)
+     -> io::Result<()> {
+    let colorstring;
+    let pen_s;
+    let fontcolor;
+    let layer;
+    let pos;
+    let gradientangle;
+
+    if let Some(c) = g.node_comment(n) {
+        comment(w, depth, &c)?;
+    }
+
+    let id = node_id(n)?;
+    let id = id.to_dot_string();
+
+    let escaped = &g.node_label(n).to_dot_string();
+    let shape;
+    let image;
+    let size;
+    let margin;
+    let svg_id;
+    let attr_comment;
+    let group;
+
+    let mut text = vec![&*id];
+
+    if !options.contains(&RenderOption::NoNodeLabels) {
+        text.push("[label=");
+        text.push(escaped);
+        text.push("]");
+    }
+
+    let pen = g.node_pen(n);
+    if let Some(ref p) = pen {
+        pen_s = p.to_dot_string(!options.contains(&RenderOption::NoNodeColors),
+                                !options.contains(&RenderOption::NoNodeStyles));
+        text.push(&pen_s);
+    }
+
+    let style = g.node_style(n);
+    if pen.is_none() && !options.contains(&RenderOption::NoNodeStyles) && style != Style::None {
+        text.push("[style=\"");
+        text.push(style.as_slice());
+        text.push("\"]");
+    }
+
+    let color = g.node_color(n);
+    if pen.is_none() && !options.contains(&RenderOption::NoNodeColors) {
+        if let Some(c) = color {
+            colorstring = c.to_dot_string();
+            text.push("[color=");
+            text.push(&colorstring);
+            text.push("]");
+        }
+    }
+
+    if let Some(c) = g.node_fontcolor(n) {
+        fontcolor = c.to_dot_string();
+        text.push("[fontcolor=");
+        text.push(&fontcolor);
+        text.push("]");
+    }
+
+    if let Some(s) = g.node_shape_typed(n) {
+        text.push("[shape=\"");
+        text.push(s.as_slice());
+        text.push("\"]");
+    } else if let Some(s) = g.node_shape(n) {
+        shape = match s {
+            HtmlStr(s) => LabelStr(s),
+            s => s,
+        }.to_dot_string();
+        text.push("[shape=");
+        text.push(&shape);
+        text.push("]");
+    }
+
+    if let Some(i) = g.node_image(n) {
+        image = LabelText::label(i).to_dot_string();
+        text.push("[image=");
+        text.push(&image);
+        text.push("]");
+    }
+
+    if let Some(pos) = g.node_imagepos(n) {
+        text.push("[imagepos=\"");
+        text.push(pos.as_slice());
+        text.push("\"]");
+    }
+
+    if let Some(s) = g.node_size(n) {
+        size = s.to_dot_string();
+        text.push(&size);
+    }
+
+    if let Some((x, y)) = g.node_margin(n).filter(|&(x, y)| x.is_finite() && y.is_finite()) {
+        margin = format!("[margin=\"{},{}\"]", x, y);
+        text.push(&margin);
+    }
+
+    if let Some(i) = g.node_svg_id(n) {
+        svg_id = i.to_dot_string().into_owned();
+        text.push("[id=");
+        text.push(&svg_id);
+        text.push("]");
+    }
+
+    if let Some(c) = g.node_attr_comment(n) {
+        attr_comment = LabelText::label(c).to_dot_string();
+        text.push("[comment=");
+        text.push(&attr_comment);
+        text.push("]");
+    }
+
+    if let Some(i) = g.node_group(n) {
+        group = i.to_dot_string().into_owned();
+        text.push("[group=");
+        text.push(&group);
+        text.push("]");
+    }
+
+    if g.node_nojustify(n) {
+        text.push("[nojustify=true]");
+    }
+
+    if let Some(l) = g.node_layer(n) {
+        layer = LabelText::label(l).to_dot_string();
+        text.push("[layer=");
+        text.push(&layer);
+        text.push("]");
+    }
+
+    if let Some(p) = g.node_pos(n) {
+        pos = LabelText::label(p).to_dot_string();
+        text.push("[pos=");
+        text.push(&pos);
+        text.push("]");
+    }
+
+    if let Some(angle) = g.node_gradientangle(n).filter(|a| a.is_finite()) {
+        gradientangle = format!("[gradientangle={}]", angle);
+        text.push(&gradientangle);
+    }
+
+    let node_attrs = g.node_attrs(n).iter().map(|(name, value)| format!("[{name}={value}]")).collect::<Vec<String>>();
+    text.extend(node_attrs.iter().map(|s| s as &str));
+
+    if options.contains(&RenderOption::Canonical) || options.contains(&RenderOption::MergeAttributes) {
+        return statement(w, depth, hook, StatementKind::Node, &[&rewrite_statement(&text.concat(), options)]);
+    }
+    statement(w, depth, hook, StatementKind::Node, &text)
+}
+
+fn write_edges<'a,
+               N: Clone + 'a,
+               E: Clone + 'a,
+               G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+               W: Write,
+               F: Fn(&N) -> io::Result<Id<'a>>,
+               H: FnMut(StatementKind, &mut String)>
+    (g: &'a G,
+     w: &mut W,
+     kind: Kind,
+     options: &[RenderOption],
+     node_id: &F,
+     hook: &mut H)
+     -> io::Result<()> {
+    let mut edges = g.edges();
+    if options.contains(&RenderOption::SortEdges) || options.contains(&RenderOption::Canonical) {
+        // Parallel edges are ordered by their rendered statements, so
+        // that the output does not depend on their input order.
+        let mut keyed = edges.iter()
+            .map(|e| {
+                let source = node_id(&g.source(e))?.name().into_owned();
+                let target = node_id(&g.target(e))?.name().into_owned();
+                let mut rendered = Vec::new();
+                write_edge(g, &mut rendered, kind, e, options, node_id, &mut |_, _| {})?;
+                Ok(((source, target, rendered), e.clone()))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        edges = keyed.into_iter().map(|(_, e)| e).collect();
+    }
+
+    if options.contains(&RenderOption::MergeParallelEdges) {
+        let mut seen = HashSet::new();
+        let mut merged = Vec::with_capacity(edges.len());
+        for e in edges.iter() {
+            let source = node_id(&g.source(e))?.name().into_owned();
+            let target = node_id(&g.target(e))?.name().into_owned();
+            if seen.insert((source, target)) {
+                merged.push(e.clone());
+            }
+        }
+        edges = merged.into();
+    }
+
+    for e in edges.iter() {
+        write_edge(g, w, kind, e, options, node_id, hook)?;
+    }
+    Ok(())
+}
+
+fn write_edge<'a,
+              N: Clone + 'a,
+              E: Clone + 'a,
+              G: Labeller<'a, N, E> + GraphWalk<'a, N, E>,
+              W: Write,
+              F: Fn(&N) -> io::Result<Id<'a>>,
+              H: FnMut(StatementKind, &mut String)>
+    (g: &'a G,
+     w: &mut W,
+     kind: Kind,
+     e: &E,
+     options: &[RenderOption],
+     node_id: &F,
+     hook: &mut H)
+     -> io::Result<()> {
+    let colorstring;
+    let pen_s;
+    let fontcolor;
+    let layer;
+    let pos;
+    let svg_id;
+    let samehead;
+    let sametail;
+    let attr_comment;
+    let arrows;
+    let arrowsize;
+    let labeldistance;
+    let labelangle;
+    let escaped_label = &g.edge_label(e).to_dot_string();
+    let start_arrow = g.edge_start_arrow(e);
+    let end_arrow = g.edge_end_arrow(e);
+    let start_arrow_s = start_arrow.to_dot_string();
+    let end_arrow_s = end_arrow.to_dot_string();
+    let dir = g.edge_dir(e);
+
+    if let Some(c) = g.edge_comment(e) {
+        comment(w, 1, &c)?;
+    }
+
+    let source = g.source(e);
+    let target = g.target(e);
+    let source_id = node_id(&source)?;
+    let target_id = node_id(&target)?;
+    let source_id = source_id.to_dot_string();
+    let target_id = target_id.to_dot_string();
+
+    let mut text = vec![&*source_id, " ",
+                        kind.edgeop(), " ",
+                        &*target_id];
+
+    if !options.contains(&RenderOption::NoEdgeLabels) {
+        text.push("[label=");
+        text.push(escaped_label);
+        text.push("]");
+    }
+
+    let pen = g.edge_pen(e);
+    if let Some(ref p) = pen {
+        pen_s = p.to_dot_string(!options.contains(&RenderOption::NoEdgeColors),
+                                !options.contains(&RenderOption::NoEdgeStyles));
+        text.push(&pen_s);
+    }
+
+    let style = g.edge_style(e);
+    if pen.is_none() && !options.contains(&RenderOption::NoEdgeStyles) && style != Style::None {
+        text.push("[style=\"");
+        text.push(style.as_slice());
+        text.push("\"]");
+    }
+
+    let colors = g.edge_colors(e);
+    let color = if colors.is_empty() {
+        g.edge_color(e)
+    } else {
+        let colors: Vec<_> = colors.into_iter().map(LabelText::pre_escaped_content).collect();
+        Some(EscStr(colors.join(":").into()))
+    };
+    if pen.is_none() && !options.contains(&RenderOption::NoEdgeColors) {
+        if let Some(c) = color {
+            colorstring = c.to_dot_string();
+            text.push("[color=");
+            text.push(&colorstring);
+            text.push("]");
+        }
+    }
+
+    if let Some(c) = g.edge_fontcolor(e) {
+        fontcolor = c.to_dot_string();
+        text.push("[fontcolor=");
+        text.push(&fontcolor);
+        text.push("]");
+    }
+
+    if let Some(constraint) = g.edge_constraint(e) {
+        text.push(if constraint { "[constraint=true]" } else { "[constraint=false]" });
+    }
+
+    if let Some(i) = g.edge_svg_id(e) {
+        svg_id = i.to_dot_string().into_owned();
+        text.push("[id=");
+        text.push(&svg_id);
+        text.push("]");
+    }
+
+    if let Some(i) = g.edge_samehead(e) {
+        samehead = i.to_dot_string().into_owned();
+        text.push("[samehead=");
+        text.push(&samehead);
+        text.push("]");
+    }
+
+    if let Some(i) = g.edge_sametail(e) {
+        sametail = i.to_dot_string().into_owned();
+        text.push("[sametail=");
+        text.push(&sametail);
+        text.push("]");
+    }
+
+    if let Some(c) = g.edge_attr_comment(e) {
+        attr_comment = LabelText::label(c).to_dot_string();
+        text.push("[comment=");
+        text.push(&attr_comment);
+        text.push("]");
+    }
+
+    if g.edge_nojustify(e) {
+        text.push("[nojustify=true]");
+    }
+
+    if let Some(l) = g.edge_layer(e) {
+        layer = LabelText::label(l).to_dot_string();
+        text.push("[layer=");
+        text.push(&layer);
+        text.push("]");
+    }
+
+    if let Some(p) = g.edge_pos(e) {
+        pos = LabelText::label(p).to_dot_string();
+        text.push("[pos=");
+        text.push(&pos);
+        text.push("]");
+    }
+
+    let has_arrows = !options.contains(&RenderOption::NoArrows) &&
+        (!start_arrow.is_default() || !end_arrow.is_default());
+    if has_arrows {
+        let mut attrs = Vec::new();
+        if !end_arrow.is_default() {
+            attrs.push(format!("arrowhead=\"{}\"", end_arrow_s));
+        }
+        match dir {
+            Some(dir) => attrs.push(format!("dir=\"{}\"", dir.as_slice())),
+            None if !start_arrow.is_default() => attrs.push("dir=\"both\"".to_string()),
+            None => {}
+        }
+        if !start_arrow.is_default() {
+            attrs.push(format!("arrowtail=\"{}\"", start_arrow_s));
+        }
+        arrows = format!("[{}]", attrs.join(" "));
+        text.push(&arrows);
+    }
+
+    if let Some(size) = g.edge_arrowsize(e).filter(|s| s.is_finite()) {
+        arrowsize = format!("[arrowsize={}]", size);
+        text.push(&arrowsize);
+    }
+
+    if let Some(distance) = g.edge_labeldistance(e).filter(|d| d.is_finite()) {
+        labeldistance = format!("[labeldistance={}]", distance);
+        text.push(&labeldistance);
+    }
+
+    if let Some(angle) = g.edge_labelangle(e).filter(|a| a.is_finite()) {
+        labelangle = format!("[labelangle={}]", angle);
+        text.push(&labelangle);
+    }
+
+    if let Some(decorate) = g.edge_decorate(e) {
+        text.push(if decorate { "[decorate=true]" } else { "[decorate=false]" });
+    }
+
+    if let Some(labelfloat) = g.edge_labelfloat(e) {
+        text.push(if labelfloat { "[labelfloat=true]" } else { "[labelfloat=false]" });
+    }
+
+    if options.contains(&RenderOption::NoArrows) && kind == Kind::Digraph {
+        text.push("[dir=none]");
+    } else if let Some(dir) = dir.filter(|_| !has_arrows) {
+        text.push("[dir=");
+        text.push(dir.as_slice());
+        text.push("]");
+    }
+
+    let edge_attrs = g.edge_attrs(e).iter().map(|(name, value)| format!("[{name}={value}]")).collect::<Vec<String>>();
+    text.extend(edge_attrs.iter().map(|s| s as &str));
+    if options.contains(&RenderOption::Canonical) || options.contains(&RenderOption::MergeAttributes) {
+        statement(w, 1, hook, StatementKind::Edge, &[&rewrite_statement(&text.concat(), options)])
+    } else {
+        statement(w, 1, hook, StatementKind::Edge, &text)
+    }
+}
+
+/// A value that is one of two alternatives. The nodes and edges of a
+/// `Union` are `Left` when they come from its first graph, and `Right`
+/// when they come from its second graph.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+/// Two graphs combined into one, so that they can be rendered side by
+/// side in a single DOT document. See `union`.
+pub struct Union<'a, G1: 'a, G2: 'a> {
+    first: &'a G1,
+    second: &'a G2,
+    first_prefix: &'a str,
+    second_prefix: &'a str,
+}
+
+/// Combines `g1` and `g2` into a single graph implementing both
+/// `Labeller` and `GraphWalk`.
+///
+/// The node, subgraph, group, `samehead` and `sametail` identifiers of
+/// `g1` and `g2` are prefixed with `prefix1` and `prefix2` respectively
+/// to avoid collisions, so the prefixes should be distinct, and neither
+/// should start with the other. A prefixed identifier that is no longer
+/// a plain DOT identifier, e.g. a prefixed numeral, is quoted. The
+/// prefix of a cluster subgraph goes after `cluster`, so that it stays
+/// a cluster. Edges stay within the graph they come from. Graph-wide
+/// properties, such as the identifier, the kind and the layout
+/// attributes, are taken from `g1`.
+pub fn union<'a, G1, G2>(g1: &'a G1, g2: &'a G2, prefix1: &'a str, prefix2: &'a str)
+                         -> Union<'a, G1, G2> {
+    Union {
+        first: g1,
+        second: g2,
+        first_prefix: prefix1,
+        second_prefix: prefix2,
+    }
+}
+
+fn prefixed_id<'a>(prefix: &str, id: Id) -> Id<'a> {
+    Id::quoted(format!("{}{}", prefix, id.as_slice()))
+}
+
+fn prefixed_subgraph_id<'a>(prefix: &str, id: Id) -> Id<'a> {
+    match id.as_slice().strip_prefix("cluster") {
+        Some(name) => Id::quoted(format!("cluster{}{}", prefix, name)),
+        None => prefixed_id(prefix, id),
+    }
+}
+
+/// Reverses `prefixed_subgraph_id`, if `id` carries `prefix`.
+fn unprefixed_subgraph_id<'a>(prefix: &str, id: &'a Id) -> Option<Id<'a>> {
+    let name = id.as_slice();
+    match name.strip_prefix("cluster").and_then(|name| name.strip_prefix(prefix)) {
+        Some(name) => Some(Id::quoted(format!("cluster{}", name))),
+        None => name.strip_prefix(prefix).map(Id::quoted),
+    }
+}
+
+impl<'a, N1, E1, N2, E2, G1, G2> Labeller<'a, Either<N1, N2>, Either<E1, E2>> for Union<'a, G1, G2>
+    where G1: Labeller<'a, N1, E1>,
+          G2: Labeller<'a, N2, E2>
+{
+    forward_labeller!(union);
+
+    fn subgraph_attrs(&'a self, id: &Id) -> HashMap<Cow<'a, str>, Cow<'a, str>> {
+        let mut attrs = HashMap::new();
+        if let Some(id) = unprefixed_subgraph_id(self.second_prefix, id) {
+            attrs.extend(self.second.subgraph_attrs(&id));
+        }
+        if let Some(id) = unprefixed_subgraph_id(self.first_prefix, id) {
+            attrs.extend(self.first.subgraph_attrs(&id));
+        }
+        attrs
+    }
+
+    fn node_id(&'a self, n: &Either<N1, N2>) -> Id<'a> {
+        match *n {
+            Either::Left(ref n) => prefixed_id(self.first_prefix, self.first.node_id(n)),
+            Either::Right(ref n) => prefixed_id(self.second_prefix, self.second.node_id(n)),
+        }
+    }
+
+    fn try_node_id(&'a self, n: &Either<N1, N2>) -> Result<Id<'a>, ()> {
+        match *n {
+            Either::Left(ref n) => Ok(prefixed_id(self.first_prefix, self.first.try_node_id(n)?)),
+            Either::Right(ref n) => Ok(prefixed_id(self.second_prefix, self.second.try_node_id(n)?)),
+        }
+    }
+
+    fn node_group(&'a self, n: &Either<N1, N2>) -> Option<Id<'a>> {
+        match *n {
+            Either::Left(ref n) => self.first.node_group(n).map(|id| prefixed_id(self.first_prefix, id)),
+            Either::Right(ref n) => self.second.node_group(n).map(|id| prefixed_id(self.second_prefix, id)),
+        }
+    }
+
+    fn node_subgraph(&'a self, n: &Either<N1, N2>) -> Option<Id<'a>> {
+        match *n {
+            Either::Left(ref n) => self.first.node_subgraph(n).map(|id| prefixed_subgraph_id(self.first_prefix, id)),
+            Either::Right(ref n) => self.second.node_subgraph(n).map(|id| prefixed_subgraph_id(self.second_prefix, id)),
+        }
+    }
+
+    fn edge_samehead(&'a self, e: &Either<E1, E2>) -> Option<Id<'a>> {
+        match *e {
+            Either::Left(ref e) => self.first.edge_samehead(e).map(|id| prefixed_id(self.first_prefix, id)),
+            Either::Right(ref e) => self.second.edge_samehead(e).map(|id| prefixed_id(self.second_prefix, id)),
+        }
+    }
+
+    fn edge_sametail(&'a self, e: &Either<E1, E2>) -> Option<Id<'a>> {
+        match *e {
+            Either::Left(ref e) => self.first.edge_sametail(e).map(|id| prefixed_id(self.first_prefix, id)),
+            Either::Right(ref e) => self.second.edge_sametail(e).map(|id| prefixed_id(self.second_prefix, id)),
+        }
+    }
+}
+
+impl<'a, N1, E1, N2, E2, G1, G2> GraphWalk<'a, Either<N1, N2>, Either<E1, E2>> for Union<'a, G1, G2>
+    where N1: Clone,
+          E1: Clone,
+          N2: Clone,
+          E2: Clone,
+          G1: GraphWalk<'a, N1, E1>,
+          G2: GraphWalk<'a, N2, E2>
+{
+    fn nodes(&'a self) -> Nodes<'a, Either<N1, N2>> {
+        let first = self.first.nodes();
+        let second = self.second.nodes();
+        first.iter().cloned().map(Either::Left)
+            .chain(second.iter().cloned().map(Either::Right))
+            .collect()
+    }
+
+    fn edges(&'a self) -> Edges<'a, Either<E1, E2>> {
+        let first = self.first.edges();
+        let second = self.second.edges();
+        first.iter().cloned().map(Either::Left)
+            .chain(second.iter().cloned().map(Either::Right))
+            .collect()
+    }
+
+    fn source(&'a self, edge: &Either<E1, E2>) -> Either<N1, N2> {
+        match *edge {
+            Either::Left(ref e) => Either::Left(self.first.source(e)),
+            Either::Right(ref e) => Either::Right(self.second.source(e)),
+        }
+    }
+
+    fn target(&'a self, edge: &Either<E1, E2>) -> Either<N1, N2> {
+        match *edge {
+            Either::Left(ref e) => Either::Left(self.first.target(e)),
+            Either::Right(ref e) => Either::Right(self.second.target(e)),
+        }
+    }
+
+    fn num_nodes(&'a self) -> Option<usize> {
+        Some(self.first.num_nodes()? + self.second.num_nodes()?)
+    }
+
+    fn num_edges(&'a self) -> Option<usize> {
+        Some(self.first.num_edges()? + self.second.num_edges()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::NodeLabels::*;
+    use super::{Id, Labeller, Nodes, Edges, GraphWalk, EdgeListGraph, edge_list, nodes_from_edges, render, render_opts, render_opts_to_string, render_all, render_checked, render_subgraph, render_with_hook, render_header, render_nodes, render_edges, render_footer, StatementKind, render_to_string, render_lines, render_to_file, Style, Kind, RankDir, Splines};
+    use super::{LabelLoc, LabelJust, Pack, PackMode, Ratio, Sep, Overlap, Font, Pen, Either, union};
+    use super::{Ordering, ParseError, validate, ImagePos, NodeSize, HtmlLabel, RecordLabel, RenderOption, escape_label_string, escape_escstring, escape_html};
+    use super::LabelText::{self, LabelStr, EscStr, HtmlStr};
+    use super::{Arrow, ArrowShape, Fill, Side, Dir, Shape};
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use std::io;
+    use std::io::prelude::*;
+
+    /// each node is an index in a vector in the graph.
+    type Node = usize;
+    struct Edge {
+        from: usize,
+        to: usize,
+        label: &'static str,
+        style: Style,
+        start_arrow: Arrow,
+        end_arrow: Arrow,
+        color: Option<&'static str>,
+        colors: Vec<&'static str>,
+        constraint: Option<bool>,
+        svg_id: Option<&'static str>,
+        samehead: Option<&'static str>,
+        sametail: Option<&'static str>,
+        arrowsize: Option<f64>,
+        labeldistance: Option<f64>,
+        labelangle: Option<f64>,
+        decorate: Option<bool>,
+        labelfloat: Option<bool>,
+        dir: Option<Dir>,
+        comment: Option<&'static str>,
+        attr_comment: Option<&'static str>,
+        nojustify: bool,
+        pen: Option<(Option<&'static str>, Option<f64>, Style)>,
+        fontcolor: Option<&'static str>,
+        layer: Option<&'static str>,
+        pos: Option<&'static str>,
+    }
+
+    fn edge(from: usize, to: usize, label: &'static str, style: Style, color: Option<&'static str>) -> Edge {
+        Edge {
+            from,
+            to,
+            label,
+            style,
+            start_arrow: Arrow::default(),
+            end_arrow: Arrow::default(),
+            color,
+            colors: vec![],
+            constraint: None,
+            svg_id: None,
+            samehead: None,
+            sametail: None,
+            arrowsize: None,
+            labeldistance: None,
+            labelangle: None,
+            decorate: None,
+            labelfloat: None,
+            dir: None,
+            comment: None,
+            attr_comment: None,
+            nojustify: false,
+            pen: None,
+            fontcolor: None,
+            layer: None,
+            pos: None,
+        }
+    }
+
+    fn edge_with_arrows(from: usize, to: usize, label: &'static str, style:Style,
+        start_arrow: Arrow, end_arrow: Arrow, color: Option<&'static str>) -> Edge {
+        Edge {
+            from,
+            to,
+            label,
+            style,
+            start_arrow,
+            end_arrow,
+            color,
+            colors: vec![],
+            constraint: None,
+            svg_id: None,
+            samehead: None,
+            sametail: None,
+            arrowsize: None,
+            labeldistance: None,
+            labelangle: None,
+            decorate: None,
+            labelfloat: None,
+            dir: None,
+            comment: None,
+            attr_comment: None,
+            nojustify: false,
+            pen: None,
+            fontcolor: None,
+            layer: None,
+            pos: None,
+        }
+    }
+
+
+    struct LabelledGraph {
+        /// The name for this graph. Used for labelling generated `digraph`.
+        name: &'static str,
+
+        /// Each node is an index into `node_labels`; these labels are
+        /// used as the label text for each node. (The node *names*,
+        /// which are unique identifiers, are derived from their index
+        /// in this array.)
+        ///
+        /// If a node maps to None here, then just use its name as its
+        /// text.
+        node_labels: Vec<Option<&'static str>>,
+
+        node_styles: Vec<Style>,
+
+        /// Additional per-node attributes, indexed like `node_labels`.
+        node_extras: Vec<NodeExtra>,
+
+        /// Attributes of the subgraphs named by `NodeExtra::subgraph`,
+        /// as (subgraph, name, value) triples.
+        subgraph_attrs: Vec<(&'static str, &'static str, &'static str)>,
+
+        /// Raw statements written after the edges.
+        trailing: Vec<&'static str>,
+
+        /// The layers of the graph, if any.
+        layers: Option<Vec<&'static str>>,
+
+        /// Each edge relates a from-index to a to-index along with a
+        /// label; `edges` collects them.
+        edges: Vec<Edge>,
+    }
+
+    /// Attributes that `LabelledGraph::new` leaves unset for every node.
+    #[derive(Default)]
+    struct NodeExtra {
+        image: Option<&'static str>,
+        imagepos: Option<ImagePos>,
+        size: Option<NodeSize>,
+        margin: Option<(f64, f64)>,
+        svg_id: Option<&'static str>,
+        comment: Option<&'static str>,
+        attr_comment: Option<&'static str>,
+        group: Option<&'static str>,
+        subgraph: Option<&'static str>,
+        nojustify: bool,
+        pen: Option<(Option<&'static str>, Option<f64>, Style)>,
+        shape: Option<Shape>,
+        fontcolor: Option<&'static str>,
+        layer: Option<&'static str>,
+        pos: Option<&'static str>,
+        html_shape: Option<&'static str>,
+        gradientangle: Option<f64>,
+    }
+
+    // A simple wrapper around LabelledGraph that forces the labels to
+    // be emitted as EscStr.
+    struct LabelledGraphWithEscStrs {
+        graph: LabelledGraph,
+    }
+
+    enum NodeLabels<L> {
+        AllNodesLabelled(Vec<L>),
+        UnlabelledNodes(usize),
+        SomeNodesLabelled(Vec<Option<L>>),
+    }
+
+    type Trivial = NodeLabels<&'static str>;
+
+    impl NodeLabels<&'static str> {
+        fn into_opt_strs(self) -> Vec<Option<&'static str>> {
+            match self {
+                UnlabelledNodes(len) => vec![None; len],
+                AllNodesLabelled(lbls) => lbls.into_iter().map(Some).collect(),
+                SomeNodesLabelled(lbls) => lbls.into_iter().collect(),
+            }
+        }
+
+        fn len(&self) -> usize {
+            match self {
+                &UnlabelledNodes(len) => len,
+                AllNodesLabelled(lbls) => lbls.len(),
+                SomeNodesLabelled(lbls) => lbls.len(),
+            }
+        }
+    }
+
+    impl LabelledGraph {
+        fn new(name: &'static str,
+               node_labels: Trivial,
+               edges: Vec<Edge>,
+               node_styles: Option<Vec<Style>>)
+               -> LabelledGraph {
+            let count = node_labels.len();
+            LabelledGraph {
+                name,
+                node_labels: node_labels.into_opt_strs(),
+                edges,
+                node_styles: match node_styles {
+                    Some(nodes) => nodes,
+                    None => vec![Style::None; count],
+                },
+                node_extras: (0..count).map(|_| NodeExtra::default()).collect(),
+                subgraph_attrs: vec![],
+                trailing: vec![],
+                layers: None,
+            }
+        }
+    }
+
+    impl LabelledGraphWithEscStrs {
+        fn new(name: &'static str,
+               node_labels: Trivial,
+               edges: Vec<Edge>)
+               -> LabelledGraphWithEscStrs {
+            LabelledGraphWithEscStrs { graph: LabelledGraph::new(name, node_labels, edges, None) }
+        }
+    }
+
+    fn id_name<'a>(n: &Node) -> Id<'a> {
+        Id::new(format!("N{}", *n)).unwrap()
+    }
+
+    impl<'a> Labeller<'a, Node, &'a Edge> for LabelledGraph {
+        fn graph_id(&'a self) -> Id<'a> {
+            Id::new(self.name).unwrap()
+        }
+        fn node_id(&'a self, n: &Node) -> Id<'a> {
+            id_name(n)
+        }
+        fn node_label(&'a self, n: &Node) -> LabelText<'a> {
+            match self.node_labels[*n] {
+                Some(l) => LabelStr(l.into()),
+                None => LabelStr(id_name(n).name()),
+            }
+        }
+        fn edge_label(&'a self, e: &&'a Edge) -> LabelText<'a> {
+            LabelStr(e.label.into())
+        }
+        fn node_style(&'a self, n: &Node) -> Style {
+            self.node_styles[*n]
+        }
+        fn node_image(&'a self, n: &Node) -> Option<Cow<'a, str>> {
+            self.node_extras[*n].image.map(Cow::from)
+        }
+        fn node_imagepos(&'a self, n: &Node) -> Option<ImagePos> {
+            self.node_extras[*n].imagepos
+        }
+        fn node_size(&'a self, n: &Node) -> Option<NodeSize> {
+            self.node_extras[*n].size
+        }
+        fn node_margin(&'a self, n: &Node) -> Option<(f64, f64)> {
+            self.node_extras[*n].margin
+        }
+        fn node_shape(&'a self, n: &Node) -> Option<LabelText<'a>> {
+            self.node_extras[*n].html_shape.map(|s| HtmlStr(s.into()))
+        }
+        fn node_shape_typed(&'a self, n: &Node) -> Option<Shape> {
+            self.node_extras[*n].shape
+        }
+        fn node_fontcolor(&'a self, n: &Node) -> Option<LabelText<'a>> {
+            self.node_extras[*n].fontcolor.map(|c| LabelStr(c.into()))
+        }
+        fn node_pen(&'a self, n: &Node) -> Option<Pen<'a>> {
+            self.node_extras[*n].pen.map(|(color, width, style)| {
+                Pen { color: color.map(|c| LabelStr(c.into())), width, style }
+            })
+        }
+        fn node_svg_id(&'a self, n: &Node) -> Option<Id<'a>> {
+            self.node_extras[*n].svg_id.map(|i| Id::new(i).unwrap())
+        }
+        fn node_comment(&'a self, n: &Node) -> Option<Cow<'a, str>> {
+            self.node_extras[*n].comment.map(Cow::from)
+        }
+        fn node_attr_comment(&'a self, n: &Node) -> Option<Cow<'a, str>> {
+            self.node_extras[*n].attr_comment.map(Cow::from)
+        }
+        fn node_group(&'a self, n: &Node) -> Option<Id<'a>> {
+            self.node_extras[*n].group.map(|i| Id::new(i).unwrap())
+        }
+        fn node_subgraph(&'a self, n: &Node) -> Option<Id<'a>> {
+            self.node_extras[*n].subgraph.map(|i| Id::new(i).unwrap())
+        }
+        fn node_nojustify(&'a self, n: &Node) -> bool {
+            self.node_extras[*n].nojustify
+        }
+        fn subgraph_attrs(&'a self, id: &Id) -> HashMap<Cow<'a, str>, Cow<'a, str>> {
+            self.subgraph_attrs.iter()
+                .filter(|&&(subgraph, _, _)| subgraph == id.as_slice())
+                .map(|&(_, name, value)| (name.into(), value.into()))
+                .collect()
+        }
+        fn trailing_statements(&'a self) -> Vec<Cow<'a, str>> {
+            self.trailing.iter().map(|&s| s.into()).collect()
+        }
+        fn layers(&'a self) -> Option<Vec<Cow<'a, str>>> {
+            self.layers.as_ref().map(|layers| layers.iter().map(|&l| l.into()).collect())
+        }
+        fn node_layer(&'a self, n: &Node) -> Option<Cow<'a, str>> {
+            self.node_extras[*n].layer.map(Cow::from)
+        }
+        fn node_pos(&'a self, n: &Node) -> Option<Cow<'a, str>> {
+            self.node_extras[*n].pos.map(Cow::from)
+        }
+        fn node_gradientangle(&'a self, n: &Node) -> Option<f64> {
+            self.node_extras[*n].gradientangle
+        }
+        fn edge_style(&'a self, e: &&'a Edge) -> Style {
+            e.style
+        }
+        fn edge_color(&'a self, e: &&'a Edge) -> Option<LabelText<'a>>
+        {
+            e.color.map(|l| LabelStr((*l).into()))
+        }
+        fn edge_end_arrow(&'a self, e: &&'a Edge) -> Arrow {
+            e.end_arrow.clone()
+        }
+
+        fn edge_start_arrow(&'a self, e: &&'a Edge) -> Arrow {
+            e.start_arrow.clone()
+        }
+
+        fn edge_fontcolor(&'a self, e: &&'a Edge) -> Option<LabelText<'a>> {
+            e.fontcolor.map(|c| LabelStr(c.into()))
+        }
+
+        fn edge_layer(&'a self, e: &&'a Edge) -> Option<Cow<'a, str>> {
+            e.layer.map(Cow::from)
+        }
+
+        fn edge_pos(&'a self, e: &&'a Edge) -> Option<Cow<'a, str>> {
+            e.pos.map(Cow::from)
+        }
+
+        fn edge_pen(&'a self, e: &&'a Edge) -> Option<Pen<'a>> {
+            e.pen.map(|(color, width, style)| {
+                Pen { color: color.map(|c| LabelStr(c.into())), width, style }
+            })
+        }
+
+        fn edge_colors(&'a self, e: &&'a Edge) -> Vec<LabelText<'a>> {
+            e.colors.iter().map(|&c| LabelStr(c.into())).collect()
+        }
+
+        fn edge_constraint(&'a self, e: &&'a Edge) -> Option<bool> {
+            e.constraint
+        }
+
+        fn edge_svg_id(&'a self, e: &&'a Edge) -> Option<Id<'a>> {
+            e.svg_id.map(|i| Id::new(i).unwrap())
+        }
+
+        fn edge_samehead(&'a self, e: &&'a Edge) -> Option<Id<'a>> {
+            e.samehead.map(|i| Id::new(i).unwrap())
+        }
+
+        fn edge_sametail(&'a self, e: &&'a Edge) -> Option<Id<'a>> {
+            e.sametail.map(|i| Id::new(i).unwrap())
+        }
+
+        fn edge_arrowsize(&'a self, e: &&'a Edge) -> Option<f64> {
+            e.arrowsize
+        }
+
+        fn edge_labeldistance(&'a self, e: &&'a Edge) -> Option<f64> {
+            e.labeldistance
+        }
+
+        fn edge_labelangle(&'a self, e: &&'a Edge) -> Option<f64> {
+            e.labelangle
+        }
+
+        fn edge_decorate(&'a self, e: &&'a Edge) -> Option<bool> {
+            e.decorate
+        }
+
+        fn edge_labelfloat(&'a self, e: &&'a Edge) -> Option<bool> {
+            e.labelfloat
+        }
+
+        fn edge_dir(&'a self, e: &&'a Edge) -> Option<Dir> {
+            e.dir
+        }
+
+        fn edge_comment(&'a self, e: &&'a Edge) -> Option<Cow<'a, str>> {
+            e.comment.map(Cow::from)
+        }
+
+        fn edge_attr_comment(&'a self, e: &&'a Edge) -> Option<Cow<'a, str>> {
+            e.attr_comment.map(Cow::from)
+        }
+
+        fn edge_nojustify(&'a self, e: &&'a Edge) -> bool {
+            e.nojustify
+        }
+    }
+
+    impl<'a> Labeller<'a, Node, &'a Edge> for LabelledGraphWithEscStrs {
+        fn graph_id(&'a self) -> Id<'a> {
+            self.graph.graph_id()
+        }
+        fn node_id(&'a self, n: &Node) -> Id<'a> {
+            self.graph.node_id(n)
+        }
+        fn node_label(&'a self, n: &Node) -> LabelText<'a> {
+            match self.graph.node_label(n) {
+                LabelStr(s) | EscStr(s) | HtmlStr(s) => EscStr(s),
+            }
+        }
+        fn node_color(&'a self, n: &Node) -> Option<LabelText<'a>> {
+            match self.graph.node_color(n) {
+                Some(LabelStr(s)) | Some(EscStr(s)) | Some(HtmlStr(s)) => Some(EscStr(s)),
+                None => None,
+            }
+        }
+        fn edge_label(&'a self, e: &&'a Edge) -> LabelText<'a> {
+            match self.graph.edge_label(e) {
+                LabelStr(s) | EscStr(s) | HtmlStr(s) => EscStr(s),
+            }
+        }
+        fn edge_color(&'a self, e: &&'a Edge) -> Option<LabelText<'a>> {
+            match self.graph.edge_color(e) {
+                Some(LabelStr(s)) | Some(EscStr(s)) | Some(HtmlStr(s)) => Some(EscStr(s)),
+                None => None,
+            }
+        }
+        fn node_fontcolor(&'a self, n: &Node) -> Option<LabelText<'a>> {
+            self.graph.node_fontcolor(n)
+        }
+        fn edge_fontcolor(&'a self, e: &&'a Edge) -> Option<LabelText<'a>> {
+            self.graph.edge_fontcolor(e)
+        }
+    }
+
+    impl<'a> GraphWalk<'a, Node, &'a Edge> for LabelledGraph {
+        fn nodes(&'a self) -> Nodes<'a, Node> {
+            (0..self.node_labels.len()).collect()
+        }
+        fn edges(&'a self) -> Edges<'a, &'a Edge> {
+            self.edges.iter().collect()
+        }
+        fn source(&'a self, edge: &&'a Edge) -> Node {
+            edge.from
+        }
+        fn target(&'a self, edge: &&'a Edge) -> Node {
+            edge.to
+        }
+    }
+
+    impl<'a> GraphWalk<'a, Node, &'a Edge> for LabelledGraphWithEscStrs {
+        fn nodes(&'a self) -> Nodes<'a, Node> {
+            self.graph.nodes()
+        }
+        fn edges(&'a self) -> Edges<'a, &'a Edge> {
+            self.graph.edges()
+        }
+        fn source(&'a self, edge: &&'a Edge) -> Node {
+            edge.from
+        }
+        fn target(&'a self, edge: &&'a Edge) -> Node {
+            edge.to
+        }
+    }
+
+    fn test_input(g: LabelledGraph) -> io::Result<String> {
+        let mut writer = Vec::new();
+        render(&g, &mut writer).unwrap();
+        let mut s = String::new();
+        Read::read_to_string(&mut &*writer, &mut s)?;
+        Ok(s)
+    }
+
+    // All of the tests use raw-strings as the format for the expected outputs,
+    // so that you can cut-and-paste the content into a .dot file yourself to
+    // see what the graphviz visualizer would produce.
+
+    #[test]
+    fn empty_graph() {
+        let labels: Trivial = UnlabelledNodes(0);
+        let r = test_input(LabelledGraph::new("empty_graph", labels, vec![], None));
+        assert_eq!(r.unwrap(),
+r#"digraph empty_graph {
+}
+"#);
+    }
+
+    #[test]
     fn single_node() {
         let labels: Trivial = UnlabelledNodes(1);
-        let r = test_input(LabelledGraph::new("single_node", labels, vec![], None));
+        let r = test_input(LabelledGraph::new("single_node", labels, vec![], None));
+        assert_eq!(r.unwrap(),
+r#"digraph single_node {
+    N0[label="N0"];
+}
+"#);
+    }
+
+    #[test]
+    fn single_node_with_style() {
+        let labels: Trivial = UnlabelledNodes(1);
+        let styles = Some(vec![Style::Dashed]);
+        let r = test_input(LabelledGraph::new("single_node", labels, vec![], styles));
+        assert_eq!(r.unwrap(),
+r#"digraph single_node {
+    N0[label="N0"][style="dashed"];
+}
+"#);
+    }
+
+    #[test]
+    fn single_edge() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let result = test_input(LabelledGraph::new("single_edge",
+                                                   labels,
+                                                   vec![edge(0, 1, "E", Style::None, None)],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph single_edge {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label="E"];
+}
+"#);
+    }
+
+    #[test]
+    fn single_edge_with_style() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let result = test_input(LabelledGraph::new("single_edge",
+                                                   labels,
+                                                   vec![edge(0, 1, "E", Style::Bold, Some("red"))],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph single_edge {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label="E"][style="bold"][color="red"];
+}
+"#);
+    }
+
+    #[test]
+    fn single_edge_with_colors() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let multi = Edge { colors: vec!["red", "blue"], ..edge(0, 1, "E", Style::None, Some("green")) };
+        let result = test_input(LabelledGraph::new("single_edge", labels, vec![multi], None));
+        assert_eq!(result.unwrap(),
+r#"digraph single_edge {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label="E"][color="red:blue"];
+}
+"#);
+    }
+
+    #[test]
+    fn test_some_labelled() {
+        let labels: Trivial = SomeNodesLabelled(vec![Some("A"), None]);
+        let styles = Some(vec![Style::None, Style::Dotted]);
+        let result = test_input(LabelledGraph::new("test_some_labelled",
+                                                   labels,
+                                                   vec![edge(0, 1, "A-1", Style::None, None)],
+                                                   styles));
+        assert_eq!(result.unwrap(),
+r#"digraph test_some_labelled {
+    N0[label="A"];
+    N1[label="N1"][style="dotted"];
+    N0 -> N1[label="A-1"];
+}
+"#);
+    }
+
+    #[test]
+    fn single_cyclic_node() {
+        let labels: Trivial = UnlabelledNodes(1);
+        let r = test_input(LabelledGraph::new("single_cyclic_node",
+                                              labels,
+                                              vec![edge(0, 0, "E", Style::None, None)],
+                                              None));
+        assert_eq!(r.unwrap(),
+r#"digraph single_cyclic_node {
+    N0[label="N0"];
+    N0 -> N0[label="E"];
+}
+"#);
+    }
+
+    #[test]
+    fn single_node_with_image() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let mut g = LabelledGraph::new("single_node", labels, vec![], None);
+        g.node_extras[0].image = Some("path with space.png");
+        g.node_extras[0].imagepos = Some(ImagePos::TopLeft);
+        g.node_extras[1].image = Some("icon.png");
+        let r = test_input(g);
+        assert_eq!(r.unwrap(),
+r#"digraph single_node {
+    N0[label="N0"][image="path with space.png"][imagepos="tl"];
+    N1[label="N1"][image="icon.png"];
+}
+"#);
+    }
+
+    #[test]
+    fn single_node_with_width() {
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("single_node", labels, vec![], None);
+        g.node_extras[0].size = Some(NodeSize { width: Some(1.5), ..NodeSize::default() });
+        let r = test_input(g);
+        assert_eq!(r.unwrap(),
+r#"digraph single_node {
+    N0[label="N0"][width=1.5];
+}
+"#);
+    }
+
+    #[test]
+    fn single_node_with_fixed_size() {
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("single_node", labels, vec![], None);
+        g.node_extras[0].size = Some(NodeSize { width: Some(0.75), height: Some(2.0), fixed: true });
+        let r = test_input(g);
+        assert_eq!(r.unwrap(),
+r#"digraph single_node {
+    N0[label="N0"][width=0.75][height=2][fixedsize=true];
+}
+"#);
+
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("single_node", labels, vec![], None);
+        g.node_extras[0].size = Some(NodeSize { width: Some(f64::NAN), height: Some(1.0), fixed: false });
+        assert_eq!(test_input(g).unwrap(), "digraph single_node {\n    N0[label=\"N0\"][height=1];\n}\n");
+    }
+
+    #[test]
+    fn svg_ids() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let with_id = Edge { svg_id: Some("edge_a"), ..edge(0, 1, "E", Style::None, None) };
+        let mut g = LabelledGraph::new("svg_ids", labels, vec![with_id], None);
+        g.node_extras[0].svg_id = Some("node_a");
+        let r = test_input(g);
+        assert_eq!(r.unwrap(),
+r#"digraph svg_ids {
+    N0[label="N0"][id=node_a];
+    N1[label="N1"];
+    N0 -> N1[label="E"][id=edge_a];
+}
+"#);
+    }
+
+    #[test]
+    fn source_comments() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let commented = Edge { comment: Some("from Foo::bar"), ..edge(0, 1, "E", Style::None, None) };
+        let mut g = LabelledGraph::new("source_comments", labels, vec![commented], None);
+        g.node_extras[0].comment = Some("first node");
+        g.node_extras[1].comment = Some("spans\ntwo lines");
+        let r = test_input(g);
+        assert_eq!(r.unwrap(),
+r#"digraph source_comments {
+    // first node
+    N0[label="N0"];
+    // spans
+    // two lines
+    N1[label="N1"];
+    // from Foo::bar
+    N0 -> N1[label="E"];
+}
+"#);
+    }
+
+    #[test]
+    fn comment_attributes() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let commented = Edge { attr_comment: Some("say \"hi\""), ..edge(0, 1, "E", Style::None, None) };
+        let mut g = LabelledGraph::new("comment_attributes", labels, vec![commented], None);
+        g.node_extras[0].attr_comment = Some("meta");
+        let r = test_input(g);
+        assert_eq!(r.unwrap(),
+r#"digraph comment_attributes {
+    N0[label="N0"][comment="meta"];
+    N1[label="N1"];
+    N0 -> N1[label="E"][comment="say \"hi\""];
+}
+"#);
+    }
+
+    #[test]
+    fn node_groups() {
+        let labels: Trivial = UnlabelledNodes(4);
+        let mut g = LabelledGraph::new("node_groups", labels,
+                                       vec![edge(0, 1, "", Style::None, None),
+                                            edge(1, 2, "", Style::None, None),
+                                            edge(0, 3, "", Style::None, None)],
+                                       None);
+        for n in 0..3 {
+            g.node_extras[n].group = Some("main");
+        }
+        let r = test_input(g);
+        assert_eq!(r.unwrap(),
+r#"digraph node_groups {
+    N0[label="N0"][group=main];
+    N1[label="N1"][group=main];
+    N2[label="N2"][group=main];
+    N3[label="N3"];
+    N0 -> N1[label=""];
+    N1 -> N2[label=""];
+    N0 -> N3[label=""];
+}
+"#);
+    }
+
+    #[test]
+    fn clusters_with_attributes() {
+        let labels: Trivial = UnlabelledNodes(3);
+        let mut g = LabelledGraph::new("clusters", labels,
+                                       vec![edge(0, 1, "", Style::None, None),
+                                            edge(1, 2, "", Style::None, None)],
+                                       None);
+        g.node_extras[0].subgraph = Some("cluster_backend");
+        g.node_extras[2].subgraph = Some("cluster_backend");
+        g.subgraph_attrs = vec![("cluster_backend", "label", "Back end"),
+                                ("cluster_backend", "style", "filled"),
+                                ("cluster_backend", "fillcolor", "lightgrey")];
+        let r = test_input(g);
+        assert_eq!(r.unwrap(),
+r#"digraph clusters {
+    subgraph cluster_backend {
+        fillcolor="lightgrey";
+        label="Back end";
+        style="filled";
+        N0[label="N0"];
+        N2[label="N2"];
+    }
+    N1[label="N1"];
+    N0 -> N1[label=""];
+    N1 -> N2[label=""];
+}
+"#);
+    }
+
+    #[test]
+    fn pens() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let styles = Some(vec![Style::Dotted, Style::None]);
+        let mut g = LabelledGraph::new("pens", labels,
+                                       vec![Edge { pen: Some((None, Some(2.5), Style::None)),
+                                                   ..edge(0, 1, "", Style::Dashed, Some("red")) }],
+                                       styles);
+        g.node_extras[0].pen = Some((Some("blue"), Some(0.5), Style::Bold));
+        assert_eq!(test_input(g).unwrap(),
+r#"digraph pens {
+    N0[label="N0"][color="blue"][penwidth=0.5][style="bold"];
+    N1[label="N1"];
+    N0 -> N1[label=""][penwidth=2.5];
+}
+"#);
+
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("pens", labels, vec![], None);
+        g.node_extras[0].pen = Some((None, Some(f64::INFINITY), Style::Bold));
+        assert_eq!(test_input(g).unwrap(), "digraph pens {\n    N0[label=\"N0\"][style=\"bold\"];\n}\n");
+    }
+
+    #[test]
+    fn radial_gradient_nodes() {
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("radial", labels, vec![], Some(vec![Style::Radial]));
+        g.node_extras[0].gradientangle = Some(90.0);
+        assert_eq!(test_input(g).unwrap(),
+r#"digraph radial {
+    N0[label="N0"][style="radial"][gradientangle=90];
+}
+"#);
+
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("radial", labels, vec![], Some(vec![Style::Radial]));
+        g.node_extras[0].gradientangle = Some(f64::NAN);
+        assert_eq!(test_input(g).unwrap(), "digraph radial {\n    N0[label=\"N0\"][style=\"radial\"];\n}\n");
+    }
+
+    #[test]
+    fn tapered_edges() {
+        let labels: Trivial = UnlabelledNodes(3);
+        let g = LabelledGraph::new("tapered", labels,
+                                   vec![Edge { pen: Some((None, Some(7.0), Style::Tapered)),
+                                               ..edge_with_arrows(0, 1, "", Style::None,
+                                                                  Arrow::default(), Arrow::none(), None) },
+                                        edge(1, 2, "", Style::Tapered, None)],
+                                   None);
+        assert_eq!(test_input(g).unwrap(),
+r#"digraph tapered {
+    N0[label="N0"];
+    N1[label="N1"];
+    N2[label="N2"];
+    N0 -> N1[label=""][penwidth=7][style="tapered"][arrowhead="none"];
+    N1 -> N2[label=""][style="tapered"];
+}
+"#);
+    }
+
+    #[test]
+    fn trailing_statements() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let mut g = LabelledGraph::new("trailing", labels, vec![edge(0, 1, "", Style::None, None)], None);
+        g.trailing = vec!["{rank=same; N0; N1}"];
+        assert_eq!(test_input(g).unwrap(),
+r#"digraph trailing {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label=""];
+    {rank=same; N0; N1}
+}
+"#);
+
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("trailing", labels, vec![], None);
+        g.trailing = vec!["label=\"}\"", "} digraph other {"];
+        let mut writer = Vec::new();
+        let err = render(&g, &mut writer).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("trailing", labels, vec![], None);
+        g.trailing = vec!["x[label=<{x>];", "y; // {", "/* } */ z;", "w;\n# }"];
+        assert!(test_input(g).is_ok());
+        assert!(!super::balanced_braces("# }"));
+    }
+
+    #[test]
+    fn typed_shapes() {
+        assert_eq!(Shape::Box.as_slice(), "box");
+        assert_eq!(Shape::DoubleCircle.as_slice(), "doublecircle");
+        assert_eq!(Shape::Mrecord.as_slice(), "Mrecord");
+        assert_eq!(Shape::Plaintext.as_slice(), "plaintext");
+        assert_eq!(Shape::None_.as_slice(), "none");
+
+        let labels: Trivial = UnlabelledNodes(2);
+        let mut g = LabelledGraph::new("typed_shapes", labels, vec![], None);
+        g.node_extras[0].shape = Some(Shape::Diamond);
+        assert_eq!(test_input(g).unwrap(),
+r#"digraph typed_shapes {
+    N0[label="N0"][shape="diamond"];
+    N1[label="N1"];
+}
+"#);
+    }
+
+    #[test]
+    fn layered_graph() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let mut g = LabelledGraph::new("layers", labels,
+                                       vec![Edge { layer: Some("all"), ..edge(0, 1, "", Style::None, None) }],
+                                       None);
+        g.layers = Some(vec!["base", "overlay"]);
+        g.node_extras[1].layer = Some("overlay");
+        assert_eq!(test_input(g).unwrap(),
+r#"digraph layers {
+    layers="base:overlay";
+    N0[label="N0"];
+    N1[label="N1"][layer="overlay"];
+    N0 -> N1[label=""][layer="all"];
+}
+"#);
+    }
+
+    #[test]
+    fn html_node_shape_is_bare() {
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("html_shape", labels, vec![], None);
+        g.node_extras[0].html_shape = Some("none");
+        let r = test_input(g).unwrap();
+        assert!(!r.contains("shape=<"), "{}", r);
+        assert_eq!(r,
+r#"digraph html_shape {
+    N0[label="N0"][shape="none"];
+}
+"#);
+    }
+
+    #[test]
+    fn pinned_positions() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let mut g = LabelledGraph::new("positions", labels,
+                                       vec![Edge { pos: Some("e,72,18 36,90 54,54 72,18"), ..edge(0, 1, "", Style::None, None) }],
+                                       None);
+        g.node_extras[0].pos = Some("36,90!");
+        g.node_extras[1].pos = Some("72,18");
+        assert_eq!(test_input(g).unwrap(),
+r#"digraph positions {
+    N0[label="N0"][pos="36,90!"];
+    N1[label="N1"][pos="72,18"];
+    N0 -> N1[label=""][pos="e,72,18 36,90 54,54 72,18"];
+}
+"#);
+    }
+
+    #[test]
+    fn hasse_diagram() {
+        let labels = AllNodesLabelled(vec!("{x,y}", "{x}", "{y}", "{}"));
+        let r = test_input(LabelledGraph::new("hasse_diagram",
+                                              labels,
+                                              vec![edge(0, 1, "", Style::None, Some("green")),
+                                                   edge(0, 2, "", Style::None, Some("blue")),
+                                                   edge(1, 3, "", Style::None, Some("red")),
+                                                   edge(2, 3, "", Style::None, Some("black"))],
+                                              None));
+        assert_eq!(r.unwrap(),
+r#"digraph hasse_diagram {
+    N0[label="{x,y}"];
+    N1[label="{x}"];
+    N2[label="{y}"];
+    N3[label="{}"];
+    N0 -> N1[label=""][color="green"];
+    N0 -> N2[label=""][color="blue"];
+    N1 -> N3[label=""][color="red"];
+    N2 -> N3[label=""][color="black"];
+}
+"#);
+    }
+
+    #[test]
+    fn left_aligned_text() {
+        let labels = AllNodesLabelled(vec!(
+            "if test {\
+           \\l    branch1\
+           \\l} else {\
+           \\l    branch2\
+           \\l}\
+           \\lafterward\
+           \\l",
+            "branch1",
+            "branch2",
+            "afterward"));
+
+        let mut writer = Vec::new();
+
+        let g = LabelledGraphWithEscStrs::new("syntax_tree",
+                                              labels,
+                                              vec![edge(0, 1, "then", Style::None, None),
+                                                   edge(0, 2, "else", Style::None, None),
+                                                   edge(1, 3, ";", Style::None, None),
+                                                   edge(2, 3, ";", Style::None, None)]);
+
+        render(&g, &mut writer).unwrap();
+        let mut r = String::new();
+        Read::read_to_string(&mut &*writer, &mut r).unwrap();
+
+        assert_eq!(r,
+r#"digraph syntax_tree {
+    N0[label="if test {\l    branch1\l} else {\l    branch2\l}\lafterward\l"];
+    N1[label="branch1"];
+    N2[label="branch2"];
+    N3[label="afterward"];
+    N0 -> N1[label="then"];
+    N0 -> N2[label="else"];
+    N1 -> N3[label=";"];
+    N2 -> N3[label=";"];
+}
+"#);
+    }
+
+    #[test]
+    fn non_ascii_labels() {
+        assert_eq!(LabelText::label("café\nline").to_dot_string(), "\"café\\nline\"");
+        assert_eq!(LabelText::escaped("café\nline").to_dot_string(), "\"café\\nline\"");
+        assert_eq!(LabelText::label("cafe\u{301}\t").to_dot_string(), "\"cafe\u{301}\\t\"");
+        assert_eq!(LabelText::label("🦀 crab").to_dot_string(), "\"🦀 crab\"");
+    }
+
+    #[test]
+    fn record_labels() {
+        let record = RecordLabel::new().field("f0", "a").field("f1", "b|c");
+        assert_eq!(record.to_record(), r"<f0> a|<f1> b\|c");
+        assert_eq!(record.clone().into_label().to_dot_string(), r#""<f0> a|<f1> b\|c""#);
+        assert_eq!(record.clone().vertical(None).to_record(), r"{<f0> a|<f1> b\|c}");
+        assert_eq!(record.clone().vertical(Some(RankDir::LeftRight)).to_record(), r"<f0> a|<f1> b\|c");
+        assert_eq!(record.horizontal(Some(RankDir::RightLeft)).to_record(), r"{<f0> a|<f1> b\|c}");
+
+        let nested = RecordLabel::new()
+            .text("{x}")
+            .nested(RecordLabel::new().field("in", "<in>").text("\"q\""));
+        assert_eq!(nested.into_label().to_dot_string(), r#""\{x\}|{<in> \<in\>|\"q\"}""#);
+    }
+
+    #[test]
+    fn html_escaping_keeps_entities() {
+        assert_eq!(escape_html("a &amp; b"), "a &amp; b");
+        assert_eq!(escape_html("a & b"), "a &amp; b");
+        assert_eq!(escape_html("&#169; &#xA9; &copy"), "&#169; &#xA9; &amp;copy");
+        assert_eq!(escape_html("<&;>"), "&lt;&amp;;&gt;");
+        assert_eq!(LabelText::html_escaped("x < y & z").to_dot_string(), "<x &lt; y &amp; z>");
+        assert_eq!(LabelText::html_raw("<B>a &amp; b</B>").to_dot_string(), "<<B>a &amp; b</B>>");
+    }
+
+    #[test]
+    fn public_escaping_matches_labels() {
+        for s in &["plain", "say \"hi\"", "left\\lright", "tab\tnewline\n", "caf\u{e9}"] {
+            assert_eq!(format!("\"{}\"", escape_label_string(s)), LabelStr((*s).into()).to_dot_string());
+            assert_eq!(format!("\"{}\"", escape_escstring(s)), EscStr((*s).into()).to_dot_string());
+        }
+    }
+
+    #[test]
+    fn record_safe_labels() {
+        assert_eq!(LabelText::record_safe("a|b{c}").to_dot_string(), r#""a\|b\{c\}""#);
+        assert_eq!(LabelText::record_safe("<p> \"x\"\\y").to_dot_string(), r#""\<p\> \"x\"\\y""#);
+    }
+
+    #[test]
+    fn strict_escaped_labels() {
+        assert_eq!(LabelText::escaped_strict("C:\\path").to_dot_string(), r#""C:\\path""#);
+        assert_eq!(LabelText::escaped_strict("a\\lb").to_dot_string(), r#""a\lb""#);
+        assert_eq!(LabelText::escaped_strict(r"\{x\} \\ \|").to_dot_string(), r#""\{x\} \\ \|""#);
+        assert_eq!(LabelText::escaped_strict(r#"say \"hi\""#).to_dot_string(), r#""say \"hi\"""#);
+        assert_eq!(LabelText::escaped_strict("trailing\\").to_dot_string(), r#""trailing\\""#);
+        assert_eq!(LabelText::escaped_strict("plain").to_dot_string(), r#""plain""#);
+    }
+
+    #[test]
+    fn wrapped_labels() {
+        let wrapped = LabelText::wrap("The quick brown fox jumps over the lazy dog", 20);
+        assert_eq!(wrapped.to_dot_string(), r#""The quick brown fox\ljumps over the lazy\ldog\l""#);
+        assert_eq!(LabelText::wrap("a verylongwordindeed b", 4).to_dot_string(),
+                   r#""a\lverylongwordindeed\lb\l""#);
+    }
+
+    #[test]
+    fn nojustify_nodes_and_edges() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let mut g = LabelledGraph::new("nojustify", labels,
+                                       vec![Edge { nojustify: true, ..edge(0, 1, "E", Style::None, None) }],
+                                       None);
+        g.node_extras[0].nojustify = true;
+        assert_eq!(test_input(g).unwrap(),
+r#"digraph nojustify {
+    N0[label="N0"][nojustify=true];
+    N1[label="N1"];
+    N0 -> N1[label="E"][nojustify=true];
+}
+"#);
+    }
+
+    #[test]
+    fn justified_lines() {
+        assert_eq!(LabelText::label("left").left_justify_line().to_dot_string(), r#""left\l""#);
+        assert_eq!(LabelText::label("right").right_justify_line().to_dot_string(), r#""right\r""#);
+        assert_eq!(LabelText::label("center").center_line().to_dot_string(), r#""center\n""#);
+        assert_eq!(LabelText::label("a\\b").left_justify_line().to_dot_string(), r#""a\\b\l""#);
+
+        let two_lines = LabelText::label("first").left_justify_line()
+            .suffix_line(LabelText::escaped("second").right_justify_line());
+        assert_eq!(two_lines.to_dot_string(), r#""first\l\n\nsecond\r""#);
+    }
+
+    #[test]
+    fn html_table_label() {
+        let label = HtmlLabel::table()
+            .row().cell("a & b").port_cell("out", "<next>")
+            .row().cell("\"quoted\"")
+            .into_label();
+        assert_eq!(label.to_dot_string(),
+                   "<<TABLE><TR><TD>a &amp; b</TD><TD PORT=\"out\">&lt;next&gt;</TD></TR>\
+                    <TR><TD>&quot;quoted&quot;</TD></TR></TABLE>>");
+    }
+
+    #[test]
+    fn html_table_cells_are_literal() {
+        let label = HtmlLabel::table()
+            .row().cell("&amp;").port_cell("&lt;", "&#169;")
+            .into_label();
+        assert_eq!(label.to_dot_string(),
+                   "<<TABLE><TR><TD>&amp;amp;</TD><TD PORT=\"&amp;lt;\">&amp;#169;</TD></TR></TABLE>>");
+    }
+
+    #[test]
+    fn simple_id_construction() {
+        let id1 = Id::new("hello");
+        match id1 {
+            Ok(_) => {}
+            Err(..) => panic!("'hello' is not a valid value for id anymore"),
+        }
+    }
+
+    struct EdgesOnlyGraph {
+        edges: Vec<SimpleEdge>,
+    }
+
+    impl<'a> Labeller<'a, Node, &'a SimpleEdge> for EdgesOnlyGraph {
+        fn graph_id(&'a self) -> Id<'a> {
+            Id::new("edges_only").unwrap()
+        }
+        fn node_id(&'a self, n: &Node) -> Id<'a> {
+            id_name(n)
+        }
+    }
+
+    impl<'a> EdgeListGraph<'a, Node, &'a SimpleEdge> for EdgesOnlyGraph {
+        fn edges(&'a self) -> Edges<'a, &'a SimpleEdge> {
+            self.edges.iter().collect()
+        }
+        fn source(&'a self, edge: &&'a SimpleEdge) -> Node {
+            edge.0
+        }
+        fn target(&'a self, edge: &&'a SimpleEdge) -> Node {
+            edge.1
+        }
+    }
+
+    #[test]
+    fn nodes_derived_from_edges() {
+        let g = EdgesOnlyGraph { edges: vec![(3, 1), (1, 0), (3, 3)] };
+        assert_eq!(nodes_from_edges(&edge_list(&g)).into_owned(), vec![0, 1, 3]);
+        assert_eq!(render_to_string(&edge_list(&g)).unwrap(),
+r#"digraph edges_only {
+    N0[label="N0"];
+    N1[label="N1"];
+    N3[label="N3"];
+    N3 -> N1[label=""];
+    N1 -> N0[label=""];
+    N3 -> N3[label=""];
+}
+"#);
+    }
+
+    #[test]
+    fn quoted_id_construction() {
+        assert_eq!(Id::quoted("hello").as_slice(), "hello");
+        assert_eq!(Id::quoted("-1.5").as_slice(), "-1.5");
+        assert_eq!(Id::quoted("hello").to_dot_string(), "hello");
+        assert_eq!(Id::quoted("a b").as_slice(), "a b");
+        assert_eq!(Id::quoted("a b").to_dot_string(), r#""a b""#);
+        assert_eq!(Id::quoted("say \"hi\"").to_dot_string(), r#""say \"hi\"""#);
+        assert_eq!(Id::quoted("Node").to_dot_string(), r#""Node""#);
+        assert_eq!(Id::quoted("").to_dot_string(), "\"\"");
+    }
+
+    struct QuotedIdGraph {
+        names: Vec<&'static str>,
+        edges: Vec<SimpleEdge>,
+    }
+
+    impl<'a> Labeller<'a, Node, &'a SimpleEdge> for QuotedIdGraph {
+        fn graph_id(&'a self) -> Id<'a> {
+            Id::new("quoted").unwrap()
+        }
+        fn node_id(&'a self, n: &Node) -> Id<'a> {
+            Id::quoted(self.names[*n])
+        }
+    }
+
+    impl<'a> GraphWalk<'a, Node, &'a SimpleEdge> for QuotedIdGraph {
+        fn nodes(&'a self) -> Nodes<'a, Node> {
+            (0..self.names.len()).collect()
+        }
+        fn edges(&'a self) -> Edges<'a, &'a SimpleEdge> {
+            self.edges.iter().collect()
+        }
+        fn source(&'a self, edge: &&'a SimpleEdge) -> Node {
+            edge.0
+        }
+        fn target(&'a self, edge: &&'a SimpleEdge) -> Node {
+            edge.1
+        }
+    }
+
+    #[test]
+    fn quoted_node_ids() {
+        let g = QuotedIdGraph {
+            names: vec!["a b", "plain", "x[1]"],
+            edges: vec![(0, 1), (2, 0)],
+        };
+        assert_eq!(render_to_string(&g).unwrap(),
+r#"digraph quoted {
+    "a b"[label="a b"];
+    plain[label="plain"];
+    "x[1]"[label="x[1]"];
+    "a b" -> plain[label=""];
+    "x[1]" -> "a b"[label=""];
+}
+"#);
+        let canonical = render_opts_to_string(&g, &[RenderOption::Canonical]).unwrap();
+        assert!(canonical.contains("\n    \"x[1]\"[label=\"x[1]\"];\n"), "{}", canonical);
+    }
+
+    #[test]
+    fn flipped_arrow_sides() {
+        assert_eq!(ArrowShape::Crow(Side::Left).flip_side().to_dot_string(), "rcrow");
+        assert_eq!(ArrowShape::Normal(Fill::Open, Side::Right).flip_side().to_dot_string(), "olnormal");
+        assert_eq!(ArrowShape::crow().flip_side(), ArrowShape::crow());
+        assert_eq!(ArrowShape::none().flip_side(), ArrowShape::none());
+
+        let arrow = Arrow::from([ArrowShape::Tee(Side::Left), ArrowShape::Dot(Fill::Filled, Side::Both)]);
+        assert_eq!(arrow.flip_sides().to_dot_string(), "rteedot");
+    }
+
+    #[test]
+    fn arrows_from_shapes() {
+        let single: Arrow = ArrowShape::crow().into();
+        assert!(single == Arrow::from_arrow(ArrowShape::crow()));
+        assert_eq!(single.to_dot_string(), "crow");
+
+        let shapes = [ArrowShape::tee(), ArrowShape::Dot(Fill::Open, Side::Both)];
+        let multiple: Arrow = shapes.to_vec().into();
+        assert_eq!(multiple.to_dot_string(), Arrow::from(shapes).to_dot_string());
+        assert_eq!(multiple.to_dot_string(), "teeodot");
+    }
+
+    #[test]
+    fn test_some_arrow() {
+        let labels: Trivial = SomeNodesLabelled(vec![Some("A"), None]);
+        let styles = Some(vec![Style::None, Style::Dotted]);
+        let start  = Arrow::default();
+        let end    = Arrow::from_arrow(ArrowShape::crow());
+        let result = test_input(LabelledGraph::new("test_some_labelled",
+                                                   labels,
+                                                   vec![edge_with_arrows(0, 1, "A-1", Style::None, start, end, None)],
+                                                   styles));
+        assert_eq!(result.unwrap(),
+r#"digraph test_some_labelled {
+    N0[label="A"];
+    N1[label="N1"][style="dotted"];
+    N0 -> N1[label="A-1"][arrowhead="crow"];
+}
+"#);
+    }
+
+    #[test]
+    fn test_default_and_none_arrows() {
+        let labels: Trivial = UnlabelledNodes(3);
+        let result = test_input(LabelledGraph::new("test_default_and_none_arrows",
+                                                   labels,
+                                                   vec![edge_with_arrows(0, 1, "", Style::None,
+                                                                         Arrow::default(), Arrow::default(), None),
+                                                        edge_with_arrows(1, 2, "", Style::None,
+                                                                         Arrow::default(), Arrow::none(), None)],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph test_default_and_none_arrows {
+    N0[label="N0"];
+    N1[label="N1"];
+    N2[label="N2"];
+    N0 -> N1[label=""];
+    N1 -> N2[label=""][arrowhead="none"];
+}
+"#);
+    }
+
+    #[test]
+    fn test_some_arrows() {
+        let labels: Trivial = SomeNodesLabelled(vec![Some("A"), None]);
+        let styles = Some(vec![Style::None, Style::Dotted]);
+        let start  = Arrow::from_arrow(ArrowShape::tee());
+        let end    = Arrow::from_arrow(ArrowShape::Crow(Side::Left));
+        let result = test_input(LabelledGraph::new("test_some_labelled",
+                                                   labels,
+                                                   vec![edge_with_arrows(0, 1, "A-1", Style::None, start, end, None)],
+                                                   styles));
+        assert_eq!(result.unwrap(),
+r#"digraph test_some_labelled {
+    N0[label="A"];
+    N1[label="N1"][style="dotted"];
+    N0 -> N1[label="A-1"][arrowhead="lcrow" dir="both" arrowtail="tee"];
+}
+"#);
+    }
+
+    #[test]
+    fn test_no_arrows() {
+        let labels: Trivial = UnlabelledNodes(3);
+        let g = LabelledGraph::new("test_no_arrows",
+                                   labels,
+                                   vec![edge(0, 1, "", Style::None, None),
+                                        edge_with_arrows(1, 2, "", Style::None,
+                                                         Arrow::from_arrow(ArrowShape::tee()),
+                                                         Arrow::from_arrow(ArrowShape::crow()),
+                                                         None)],
+                                   None);
+        let mut writer = Vec::new();
+        render_opts(&g, &mut writer, &[RenderOption::NoArrows]).unwrap();
+        assert_eq!(String::from_utf8(writer).unwrap(),
+r#"digraph test_no_arrows {
+    N0[label="N0"];
+    N1[label="N1"];
+    N2[label="N2"];
+    N0 -> N1[label=""][dir=none];
+    N1 -> N2[label=""][dir=none];
+}
+"#);
+    }
+
+    #[test]
+    fn test_arrowsize() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let result = test_input(LabelledGraph::new("test_arrowsize",
+                                                   labels,
+                                                   vec![Edge { arrowsize: Some(0.6), ..edge(0, 1, "", Style::None, None) },
+                                                        Edge { arrowsize: Some(2.0),
+                                                               ..edge_with_arrows(1, 0, "", Style::None,
+                                                                                  Arrow::default(),
+                                                                                  Arrow::from_arrow(ArrowShape::vee()),
+                                                                                  None) }],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph test_arrowsize {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label=""][arrowsize=0.6];
+    N1 -> N0[label=""][arrowhead="vee"][arrowsize=2];
+}
+"#);
+
+        let labels: Trivial = UnlabelledNodes(2);
+        let g = LabelledGraph::new("nan_size", labels,
+                                   vec![Edge { arrowsize: Some(f64::NAN), ..edge(0, 1, "", Style::None, None) }],
+                                   None);
+        assert!(!test_input(g).unwrap().contains("arrowsize"));
+    }
+
+    #[test]
+    fn test_label_distance_and_angle() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let result = test_input(LabelledGraph::new("test_label_placement",
+                                                   labels,
+                                                   vec![Edge { labeldistance: Some(1.5),
+                                                               labelangle: Some(-25.0),
+                                                               ..edge(0, 1, "", Style::None, None) }],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph test_label_placement {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label=""][labeldistance=1.5][labelangle=-25];
+}
+"#);
+
+        let labels: Trivial = UnlabelledNodes(2);
+        let g = LabelledGraph::new("test_label_placement", labels,
+                                   vec![Edge { labeldistance: Some(f64::INFINITY),
+                                               labelangle: Some(f64::NAN),
+                                               ..edge(0, 1, "", Style::None, None) }],
+                                   None);
+        let r = test_input(g).unwrap();
+        assert!(!r.contains("labeldistance") && !r.contains("labelangle"), "{}", r);
+    }
+
+    #[test]
+    fn test_decorate_and_labelfloat() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let result = test_input(LabelledGraph::new("test_label_styling",
+                                                   labels,
+                                                   vec![Edge { decorate: Some(true),
+                                                               labelfloat: Some(true),
+                                                               ..edge(0, 1, "uses", Style::None, None) },
+                                                        Edge { decorate: Some(false),
+                                                               ..edge(1, 0, "", Style::None, None) }],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph test_label_styling {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label="uses"][decorate=true][labelfloat=true];
+    N1 -> N0[label=""][decorate=false];
+}
+"#);
+    }
+
+    #[test]
+    fn dot_arrow_shapes() {
+        assert_eq!(ArrowShape::dot().to_dot_string(), "dot");
+        assert_eq!(ArrowShape::Dot(Fill::Open, Side::Both).to_dot_string(), "odot");
+        assert_eq!(ArrowShape::Dot(Fill::Filled, Side::Left).to_dot_string(), "ldot");
+        assert_eq!(ArrowShape::Dot(Fill::Filled, Side::Right).to_dot_string(), "rdot");
+        assert_eq!(ArrowShape::Dot(Fill::Open, Side::Left).to_dot_string(), "oldot");
+    }
+
+    #[test]
+    fn test_dot_arrows() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let start  = Arrow::from_arrow(ArrowShape::Dot(Fill::Open, Side::Right));
+        let end    = Arrow::from_arrow(ArrowShape::Dot(Fill::Filled, Side::Left));
+        let result = test_input(LabelledGraph::new("test_dot_arrows",
+                                                   labels,
+                                                   vec![edge_with_arrows(0, 1, "", Style::None, start, end, None)],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph test_dot_arrows {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label=""][arrowhead="ldot" dir="both" arrowtail="ordot"];
+}
+"#);
+    }
+
+    #[test]
+    fn test_edge_constraint() {
+        let labels: Trivial = UnlabelledNodes(3);
+        let back_edge = Edge { constraint: Some(false), ..edge(2, 0, "back", Style::None, None) };
+        let result = test_input(LabelledGraph::new("test_edge_constraint",
+                                                   labels,
+                                                   vec![edge(0, 1, "", Style::None, None),
+                                                        edge(1, 2, "", Style::None, None),
+                                                        back_edge],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph test_edge_constraint {
+    N0[label="N0"];
+    N1[label="N1"];
+    N2[label="N2"];
+    N0 -> N1[label=""];
+    N1 -> N2[label=""];
+    N2 -> N0[label="back"][constraint=false];
+}
+"#);
+    }
+
+    #[test]
+    fn style_from_str() {
+        assert_eq!("dashed".parse(), Ok(Style::Dashed));
+        assert_eq!("Filled".parse(), Ok(Style::Filled));
+        assert_eq!("".parse(), Ok(Style::None));
+        assert_eq!("wavy".parse::<Style>(), Err(ParseError::new("wavy")));
+        for &style in &[Style::None, Style::Solid, Style::Dashed, Style::Dotted, Style::Bold,
+                        Style::Rounded, Style::Diagonals, Style::Filled, Style::Striped,
+                        Style::Wedged, Style::Tapered, Style::Radial] {
+            assert_eq!(style.as_slice().parse(), Ok(style));
+        }
+    }
+
+    #[test]
+    fn rankdir_from_str() {
+        assert_eq!("LR".parse(), Ok(RankDir::LeftRight));
+        assert_eq!("bt".parse(), Ok(RankDir::BottomTop));
+        assert_eq!("".parse::<RankDir>(), Err(ParseError::new("")));
+        assert_eq!("XY".parse::<RankDir>(), Err(ParseError::new("XY")));
+        for &rankdir in &[RankDir::TopBottom, RankDir::LeftRight, RankDir::BottomTop,
+                          RankDir::RightLeft] {
+            assert_eq!(rankdir.as_slice().parse(), Ok(rankdir));
+        }
+    }
+
+    #[test]
+    fn test_samehead_sametail() {
+        let labels: Trivial = UnlabelledNodes(3);
+        let result = test_input(LabelledGraph::new("test_samehead",
+                                                   labels,
+                                                   vec![Edge { samehead: Some("h1"), ..edge(0, 2, "", Style::None, None) },
+                                                        Edge { samehead: Some("h1"), sametail: Some("t1"),
+                                                               ..edge(1, 2, "", Style::None, None) }],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph test_samehead {
+    N0[label="N0"];
+    N1[label="N1"];
+    N2[label="N2"];
+    N0 -> N2[label=""][samehead=h1];
+    N1 -> N2[label=""][samehead=h1][sametail=t1];
+}
+"#);
+    }
+
+    #[test]
+    fn numeral_id_construction() {
+        for &numeral in &["123", "-3.14", "0", ".5", "-.5", "7.", "-0"] {
+            assert_eq!(Id::new(numeral).map(|id| id.name()), Ok(numeral.into()));
+        }
+        for &invalid in &["12ab", "-", ".", "-.", "1.2.3", "--1", "1-2", "1e5", ""] {
+            assert!(Id::new(invalid).is_err(), "{:?} should not be a valid id", invalid);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::single_match)]
+    fn badly_formatted_id() {
+        let id2 = Id::new("Weird { struct : ure } !!!");
+        match id2 {
+            Ok(_) => panic!("graphviz id suddenly allows spaces, brackets and stuff"),
+            Err(..) => {}
+        }
+    }
+
+    type SimpleEdge = (Node, Node);
+
+    struct DefaultStyleGraph {
+        /// The name for this graph. Used for labelling generated graph
+        name: &'static str,
+        nodes: usize,
+        edges: Vec<SimpleEdge>,
+        kind: Kind,
+        rankdir: Option<RankDir>,
+        splines: Option<Splines>,
+        concentrate: bool,
+        newrank: bool,
+        ordering: Option<Ordering>,
+        bgcolor: Option<&'static str>,
+        size_hints: bool,
+        computed_attrs: bool,
+        label: Option<&'static str>,
+        label_loc: Option<LabelLoc>,
+        label_just: Option<LabelJust>,
+        pack: Option<Pack>,
+        packmode: Option<PackMode>,
+        size: Option<(f64, f64)>,
+        ratio: Option<Ratio>,
+        margin: Option<(f64, f64)>,
+        rotate: Option<u32>,
+        font: Option<Font<'static>>,
+        sep: Option<Sep>,
+        esep: Option<Sep>,
+        dpi: Option<f64>,
+        viewport: Option<&'static str>,
+        overlap: Option<Overlap>,
+    }
+
+    impl DefaultStyleGraph {
+        fn new(name: &'static str,
+               nodes: usize,
+               edges: Vec<SimpleEdge>,
+               kind: Kind)
+               -> DefaultStyleGraph {
+            assert!(!name.is_empty());
+            DefaultStyleGraph {
+                name,
+                nodes,
+                edges,
+                kind,
+                rankdir: None,
+                splines: None,
+                concentrate: false,
+                newrank: false,
+                ordering: None,
+                bgcolor: None,
+                size_hints: false,
+                computed_attrs: false,
+                label: None,
+                label_loc: None,
+                label_just: None,
+                pack: None,
+                packmode: None,
+                size: None,
+                ratio: None,
+                margin: None,
+                rotate: None,
+                font: None,
+                sep: None,
+                esep: None,
+                dpi: None,
+                viewport: None,
+                overlap: None,
+            }
+        }
+
+        fn with_rankdir(self, rankdir: Option<RankDir>) -> Self {
+            Self {
+                rankdir,
+                ..self
+            }
+        }
+
+        fn with_splines(self, splines: Option<Splines>) -> Self {
+            Self {
+                splines,
+                ..self
+            }
+        }
+
+        fn with_concentrate(self, concentrate: bool) -> Self {
+            Self {
+                concentrate,
+                ..self
+            }
+        }
+
+        fn with_newrank(self, newrank: bool) -> Self {
+            Self {
+                newrank,
+                ..self
+            }
+        }
+
+        fn with_ordering(self, ordering: Option<Ordering>) -> Self {
+            Self {
+                ordering,
+                ..self
+            }
+        }
+
+        fn with_bgcolor(self, bgcolor: Option<&'static str>) -> Self {
+            Self {
+                bgcolor,
+                ..self
+            }
+        }
+
+        fn with_computed_attrs(self, computed_attrs: bool) -> Self {
+            Self {
+                computed_attrs,
+                ..self
+            }
+        }
+
+        fn with_size_hints(self, size_hints: bool) -> Self {
+            Self {
+                size_hints,
+                ..self
+            }
+        }
+
+        fn with_label(self, label: Option<&'static str>,
+                      label_loc: Option<LabelLoc>,
+                      label_just: Option<LabelJust>) -> Self {
+            Self {
+                label,
+                label_loc,
+                label_just,
+                ..self
+            }
+        }
+
+        fn with_pack(self, pack: Option<Pack>, packmode: Option<PackMode>) -> Self {
+            Self {
+                pack,
+                packmode,
+                ..self
+            }
+        }
+
+        fn with_size(self, size: Option<(f64, f64)>, ratio: Option<Ratio>) -> Self {
+            Self {
+                size,
+                ratio,
+                ..self
+            }
+        }
+
+        fn with_margin(self, margin: Option<(f64, f64)>) -> Self {
+            Self {
+                margin,
+                ..self
+            }
+        }
+
+        fn with_rotate(self, rotate: Option<u32>) -> Self {
+            Self {
+                rotate,
+                ..self
+            }
+        }
+
+        fn with_font(self, font: Option<Font<'static>>) -> Self {
+            Self {
+                font,
+                ..self
+            }
+        }
+
+        fn with_sep(self, sep: Option<Sep>, esep: Option<Sep>) -> Self {
+            Self {
+                sep,
+                esep,
+                ..self
+            }
+        }
+
+        fn with_overlap(self, overlap: Option<Overlap>) -> Self {
+            Self {
+                overlap,
+                ..self
+            }
+        }
+
+        fn with_resolution(self, dpi: Option<f64>, viewport: Option<&'static str>) -> Self {
+            Self {
+                dpi,
+                viewport,
+                ..self
+            }
+        }
+    }
+
+    impl<'a> Labeller<'a, Node, &'a SimpleEdge> for DefaultStyleGraph {
+        fn graph_id(&'a self) -> Id<'a> {
+            Id::new(self.name).unwrap()
+        }
+        fn node_id(&'a self, n: &Node) -> Id<'a> {
+            id_name(n)
+        }
+        fn kind(&self) -> Kind {
+            self.kind
+        }
+        fn graph_attrs(&'a self) -> HashMap<Cow<'a, str>, Cow<'a, str>> {
+            let mut attrs = HashMap::new();
+            if self.computed_attrs {
+                attrs.insert("comment".into(), format!("\"{} nodes\"", self.nodes).into());
+            }
+            attrs
+        }
+        fn node_attrs(&'a self, n: &Node) -> HashMap<Cow<'a, str>, Cow<'a, str>> {
+            let mut attrs = HashMap::new();
+            if self.computed_attrs {
+                attrs.insert("tooltip".into(), format!("\"node {}\"", n).into());
+            }
+            attrs
+        }
+        fn rank_dir(&self) -> Option<RankDir> {
+            self.rankdir
+        }
+        fn splines(&self) -> Option<Splines> {
+            self.splines
+        }
+        fn concentrate(&self) -> bool {
+            self.concentrate
+        }
+        fn newrank(&self) -> bool {
+            self.newrank
+        }
+        fn ordering(&self) -> Option<Ordering> {
+            self.ordering
+        }
+        fn bgcolor(&'a self) -> Option<LabelText<'a>> {
+            self.bgcolor.map(LabelText::label)
+        }
+        fn graph_label(&'a self) -> Option<LabelText<'a>> {
+            self.label.map(LabelText::label)
+        }
+        fn label_loc(&'a self) -> Option<LabelLoc> {
+            self.label_loc
+        }
+        fn label_just(&'a self) -> Option<LabelJust> {
+            self.label_just
+        }
+        fn pack(&self) -> Option<Pack> {
+            self.pack
+        }
+        fn packmode(&self) -> Option<PackMode> {
+            self.packmode
+        }
+        fn size(&'a self) -> Option<(f64, f64)> {
+            self.size
+        }
+        fn ratio(&self) -> Option<Ratio> {
+            self.ratio
+        }
+        fn margin(&'a self) -> Option<(f64, f64)> {
+            self.margin
+        }
+        fn rotate(&self) -> Option<u32> {
+            self.rotate
+        }
+        fn graph_font(&'a self) -> Option<Font<'a>> {
+            self.font.clone()
+        }
+        fn sep(&self) -> Option<Sep> {
+            self.sep
+        }
+        fn esep(&self) -> Option<Sep> {
+            self.esep
+        }
+        fn overlap(&self) -> Option<Overlap> {
+            self.overlap
+        }
+        fn dpi(&self) -> Option<f64> {
+            self.dpi
+        }
+        fn viewport(&'a self) -> Option<Cow<'a, str>> {
+            self.viewport.map(Cow::from)
+        }
+    }
+
+    impl<'a> GraphWalk<'a, Node, &'a SimpleEdge> for DefaultStyleGraph {
+        fn nodes(&'a self) -> Nodes<'a, Node> {
+            (0..self.nodes).collect()
+        }
+        fn edges(&'a self) -> Edges<'a, &'a SimpleEdge> {
+            self.edges.iter().collect()
+        }
+        fn source(&'a self, edge: &&'a SimpleEdge) -> Node {
+            edge.0
+        }
+        fn target(&'a self, edge: &&'a SimpleEdge) -> Node {
+            edge.1
+        }
+        fn num_nodes(&'a self) -> Option<usize> {
+            if self.size_hints { Some(self.nodes) } else { None }
+        }
+        fn num_edges(&'a self) -> Option<usize> {
+            if self.size_hints { Some(self.edges.len()) } else { None }
+        }
+    }
+
+    fn test_input_default(g: DefaultStyleGraph) -> io::Result<String> {
+        let mut writer = Vec::new();
+        render(&g, &mut writer).unwrap();
+        let mut s = String::new();
+        Read::read_to_string(&mut &*writer, &mut s)?;
+        Ok(s)
+    }
+
+    #[test]
+    fn default_style_graph() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 4,
+                                   vec![(0, 1), (0, 2), (1, 3), (2, 3)],
+                                   Kind::Graph));
         assert_eq!(r.unwrap(),
-r#"digraph single_node {
+r#"graph g {
+    N0[label="N0"];
+    N1[label="N1"];
+    N2[label="N2"];
+    N3[label="N3"];
+    N0 -- N1[label=""];
+    N0 -- N2[label=""];
+    N1 -- N3[label=""];
+    N2 -- N3[label=""];
+}
+"#);
+    }
+
+    #[test]
+    fn default_style_digraph() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("di", 4,
+                                   vec![(0, 1), (0, 2), (1, 3), (2, 3)],
+                                   Kind::Digraph));
+        assert_eq!(r.unwrap(),
+r#"digraph di {
+    N0[label="N0"];
+    N1[label="N1"];
+    N2[label="N2"];
+    N3[label="N3"];
+    N0 -> N1[label=""];
+    N0 -> N2[label=""];
+    N1 -> N3[label=""];
+    N2 -> N3[label=""];
+}
+"#);
+    }
+
+    #[test]
+    fn digraph_with_rankdir() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("di", 4, vec![(0, 1), (0, 2)],
+                                   Kind::Digraph)
+                .with_rankdir(Some(RankDir::LeftRight)));
+        assert_eq!(
+            r.unwrap(),
+            r#"digraph di {
+    rankdir="LR";
+    N0[label="N0"];
+    N1[label="N1"];
+    N2[label="N2"];
+    N3[label="N3"];
+    N0 -> N1[label=""];
+    N0 -> N2[label=""];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn splines_as_slice() {
+        assert_eq!(Splines::None_.as_slice(), "none");
+        assert_eq!(Splines::Line.as_slice(), "line");
+        assert_eq!(Splines::Polyline.as_slice(), "polyline");
+        assert_eq!(Splines::Curved.as_slice(), "curved");
+        assert_eq!(Splines::Ortho.as_slice(), "ortho");
+        assert_eq!(Splines::Spline.as_slice(), "spline");
+    }
+
+    #[test]
+    fn graph_with_splines_and_concentrate() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 2, vec![(0, 1)], Kind::Graph)
+                .with_splines(Some(Splines::Ortho))
+                .with_concentrate(true));
+        assert_eq!(
+            r.unwrap(),
+            r#"graph g {
+    splines="ortho";
+    concentrate=true;
     N0[label="N0"];
+    N1[label="N1"];
+    N0 -- N1[label=""];
 }
-"#);
+"#
+        );
     }
 
     #[test]
-    fn single_node_with_style() {
-        let labels: Trivial = UnlabelledNodes(1);
-        let styles = Some(vec![Style::Dashed]);
-        let r = test_input(LabelledGraph::new("single_node", labels, vec![], styles));
-        assert_eq!(r.unwrap(),
-r#"digraph single_node {
-    N0[label="N0"][style="dashed"];
+    fn graph_with_newrank() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Digraph)
+                .with_newrank(true));
+        assert_eq!(
+            r.unwrap(),
+            r#"digraph g {
+    newrank=true;
+    N0[label="N0"];
 }
-"#);
+"#
+        );
+        let r = test_input_default(DefaultStyleGraph::new("g", 1, vec![], Kind::Digraph));
+        assert!(!r.unwrap().contains("newrank"));
     }
 
     #[test]
-    fn single_edge() {
-        let labels: Trivial = UnlabelledNodes(2);
-        let result = test_input(LabelledGraph::new("single_edge",
-                                                   labels,
-                                                   vec![edge(0, 1, "E", Style::None, None)],
-                                                   None));
-        assert_eq!(result.unwrap(),
-r#"digraph single_edge {
+    fn digraph_with_ordering() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("tree", 3, vec![(0, 1), (0, 2)], Kind::Digraph)
+                .with_ordering(Some(Ordering::Out)));
+        assert_eq!(
+            r.unwrap(),
+            r#"digraph tree {
+    ordering="out";
     N0[label="N0"];
     N1[label="N1"];
-    N0 -> N1[label="E"];
+    N2[label="N2"];
+    N0 -> N1[label=""];
+    N0 -> N2[label=""];
 }
-"#);
+"#
+        );
+    }
+
+    struct BadNodeIdGraph;
+
+    impl<'a> Labeller<'a, Node, &'a SimpleEdge> for BadNodeIdGraph {
+        fn graph_id(&'a self) -> Id<'a> {
+            Id::new("bad_node_id").unwrap()
+        }
+        fn node_id(&'a self, n: &Node) -> Id<'a> {
+            self.try_node_id(n).unwrap()
+        }
+        fn try_node_id(&'a self, n: &Node) -> Result<Id<'a>, ()> {
+            Id::new(format!("node {}", n))
+        }
+    }
+
+    impl<'a> GraphWalk<'a, Node, &'a SimpleEdge> for BadNodeIdGraph {
+        fn nodes(&'a self) -> Nodes<'a, Node> {
+            (0..1).collect()
+        }
+        fn edges(&'a self) -> Edges<'a, &'a SimpleEdge> {
+            Edges::Borrowed(&[])
+        }
+        fn source(&'a self, edge: &&'a SimpleEdge) -> Node {
+            edge.0
+        }
+        fn target(&'a self, edge: &&'a SimpleEdge) -> Node {
+            edge.1
+        }
     }
 
     #[test]
-    fn single_edge_with_style() {
-        let labels: Trivial = UnlabelledNodes(2);
-        let result = test_input(LabelledGraph::new("single_edge",
-                                                   labels,
-                                                   vec![edge(0, 1, "E", Style::Bold, Some("red"))],
-                                                   None));
-        assert_eq!(result.unwrap(),
-r#"digraph single_edge {
+    fn render_checked_invalid_node_id() {
+        let mut writer = Vec::new();
+        let err = render_checked(&BadNodeIdGraph, &mut writer, &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn render_checked_valid_graph() {
+        let g = DefaultStyleGraph::new("g", 2, vec![(0, 1)], Kind::Digraph);
+        let mut checked = Vec::new();
+        render_checked(&g, &mut checked, &[]).unwrap();
+        let mut plain = Vec::new();
+        render(&g, &mut plain).unwrap();
+        assert_eq!(checked, plain);
+    }
+
+    #[test]
+    fn empty_id_construction() {
+        assert!(Id::new("").is_err());
+        assert_eq!(Id::quoted("").to_dot_string(), "\"\"");
+    }
+
+    #[test]
+    fn digraph_with_bgcolor() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("dark", 1, vec![], Kind::Digraph)
+                .with_bgcolor(Some("#202020")));
+        assert_eq!(
+            r.unwrap(),
+            r##"digraph dark {
+    bgcolor="#202020";
+    N0[label="N0"];
+}
+"##
+        );
+    }
+
+    #[test]
+    fn graph_label_at_bottom() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Graph)
+                .with_label(Some("My graph"), Some(LabelLoc::Bottom), Some(LabelJust::Right)));
+        assert_eq!(
+            r.unwrap(),
+            r#"graph g {
+    label="My graph";
+    labelloc="b";
+    labeljust="r";
+    N0[label="N0"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn graph_with_pack() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 2, vec![], Kind::Graph)
+                .with_pack(Some(Pack::Enabled), Some(PackMode::Array)));
+        assert_eq!(
+            r.unwrap(),
+            r#"graph g {
+    pack=true;
+    packmode="array";
     N0[label="N0"];
     N1[label="N1"];
-    N0 -> N1[label="E"][style="bold"][color="red"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn graph_with_pack_margin() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Graph)
+                .with_pack(Some(Pack::Margin(8)), None));
+        assert_eq!(
+            r.unwrap(),
+            r#"graph g {
+    pack=8;
+    N0[label="N0"];
+}
+"#
+        );
+        assert_eq!(Pack::Disabled.to_dot_string(), "false");
+    }
+
+    #[test]
+    fn graph_with_size_and_ratio() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Digraph)
+                .with_size(Some((8.0, 11.5)), Some(Ratio::Compress)));
+        assert_eq!(
+            r.unwrap(),
+            r#"digraph g {
+    size="8,11.5";
+    ratio=compress;
+    N0[label="N0"];
+}
+"#
+        );
+        assert_eq!(Ratio::Fill.to_dot_string(), "fill");
+        assert_eq!(Ratio::Numeric(0.75).to_dot_string(), "0.75");
+
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Digraph)
+                .with_size(Some((f64::NAN, 2.0)), Some(Ratio::Numeric(f64::INFINITY))))
+            .unwrap();
+        assert!(!r.contains("size=") && !r.contains("ratio="), "{}", r);
+    }
+
+    #[test]
+    fn graph_and_node_margins() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Digraph)
+                .with_margin(Some((0.5, 0.25))));
+        assert_eq!(
+            r.unwrap(),
+            r#"digraph g {
+    margin="0.5,0.25";
+    N0[label="N0"];
+}
+"#
+        );
+
+        let labels: Trivial = UnlabelledNodes(2);
+        let mut g = LabelledGraph::new("node_margins", labels, vec![], None);
+        g.node_extras[0].margin = Some((0.2, 0.1));
+        assert_eq!(test_input(g).unwrap(),
+r#"digraph node_margins {
+    N0[label="N0"][margin="0.2,0.1"];
+    N1[label="N1"];
 }
 "#);
+
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Digraph)
+                .with_margin(Some((0.5, f64::NEG_INFINITY))));
+        assert!(!r.unwrap().contains("margin"));
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("nan_node", labels, vec![], None);
+        g.node_extras[0].margin = Some((f64::NAN, 0.1));
+        assert!(!test_input(g).unwrap().contains("margin"));
     }
 
     #[test]
-    fn test_some_labelled() {
-        let labels: Trivial = SomeNodesLabelled(vec![Some("A"), None]);
-        let styles = Some(vec![Style::None, Style::Dotted]);
-        let result = test_input(LabelledGraph::new("test_some_labelled",
-                                                   labels,
-                                                   vec![edge(0, 1, "A-1", Style::None, None)],
-                                                   styles));
-        assert_eq!(result.unwrap(),
-r#"digraph test_some_labelled {
-    N0[label="A"];
-    N1[label="N1"][style="dotted"];
-    N0 -> N1[label="A-1"];
+    fn graph_rotated_to_landscape() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Digraph)
+                .with_rotate(Some(90)));
+        assert_eq!(
+            r.unwrap(),
+            r#"digraph g {
+    rotate=90;
+    N0[label="N0"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn graph_with_font() {
+        let font = Font { name: Some("Helvetica Neue".into()), size: Some(10.5) };
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Digraph)
+                .with_font(Some(font)));
+        assert_eq!(
+            r.unwrap(),
+            r#"digraph g {
+    fontname="Helvetica Neue";
+    fontsize=10.5;
+    N0[label="N0"];
+}
+"#
+        );
+
+        let font = Font { name: None, size: Some(f64::NAN) };
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Digraph)
+                .with_font(Some(font)));
+        assert!(!r.unwrap().contains("fontsize"));
+    }
+
+    #[test]
+    fn graph_with_sep_and_esep() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Graph)
+                .with_sep(Some(Sep::Additive(4.0)), Some(Sep::Scale(0.5))));
+        assert_eq!(
+            r.unwrap(),
+            r#"graph g {
+    sep="+4";
+    esep="0.5";
+    N0[label="N0"];
+}
+"#
+        );
+        assert_eq!(Sep::Scale(1.25).to_dot_string(), "1.25");
+        assert_eq!(Sep::Additive(0.5).to_dot_string(), "+0.5");
+        assert_eq!(Sep::Additive(-1.0).to_dot_string(), "+0");
+        assert_eq!(Sep::Additive(-0.0).to_dot_string(), "+0");
+        assert_eq!(Sep::Scale(-2.0).to_dot_string(), "0");
+
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Graph)
+                .with_sep(Some(Sep::Scale(f64::NAN)), Some(Sep::Additive(f64::INFINITY))));
+        assert!(!r.unwrap().contains("sep="));
+    }
+
+    #[test]
+    fn graph_with_overlap() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Graph).with_overlap(Some(Overlap::False)));
+        assert_eq!(
+            r.unwrap(),
+            r#"graph g {
+    overlap=false;
+    N0[label="N0"];
+}
+"#
+        );
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Graph).with_overlap(Some(Overlap::Prism)));
+        assert_eq!(
+            r.unwrap(),
+            r#"graph g {
+    overlap=prism;
+    N0[label="N0"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn graph_with_dpi_and_viewport() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Graph)
+                .with_resolution(Some(150.0), Some("400,300,1.5,N0")));
+        assert_eq!(
+            r.unwrap(),
+            r#"graph g {
+    dpi=150;
+    viewport="400,300,1.5,N0";
+    N0[label="N0"];
+}
+"#
+        );
+
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 1, vec![], Kind::Graph)
+                .with_resolution(Some(f64::INFINITY), None));
+        assert!(!r.unwrap().contains("dpi"));
+    }
+
+    #[test]
+    fn union_of_two_graphs() {
+        let first = DefaultStyleGraph::new("first", 2, vec![(0, 1)], Kind::Digraph)
+            .with_size_hints(true);
+        let second = DefaultStyleGraph::new("second", 3, vec![(0, 2), (1, 2)], Kind::Digraph)
+            .with_size_hints(true);
+        let both = union(&first, &second, "a_", "b_");
+        assert_eq!(both.num_nodes(), Some(5));
+        assert_eq!(both.source(&both.edges()[1]), Either::Right(0));
+        assert_eq!(render_to_string(&both).unwrap(),
+r#"digraph first {
+    a_N0[label="N0"];
+    a_N1[label="N1"];
+    b_N0[label="N0"];
+    b_N1[label="N1"];
+    b_N2[label="N2"];
+    a_N0 -> a_N1[label=""];
+    b_N0 -> b_N2[label=""];
+    b_N1 -> b_N2[label=""];
 }
 "#);
     }
 
     #[test]
-    fn single_cyclic_node() {
-        let labels: Trivial = UnlabelledNodes(1);
-        let r = test_input(LabelledGraph::new("single_cyclic_node",
-                                              labels,
-                                              vec![edge(0, 0, "E", Style::None, None)],
-                                              None));
-        assert_eq!(r.unwrap(),
-r#"digraph single_cyclic_node {
+    fn union_keeps_quoted_and_numeric_ids() {
+        let g = QuotedIdGraph { names: vec!["a b", "-1"], edges: vec![(0, 1)] };
+        let both = union(&g, &g, "g_", "h_");
+        assert_eq!(render_to_string(&both).unwrap(),
+r#"digraph quoted {
+    "g_a b"[label="a b"];
+    "g_-1"[label="-1"];
+    "h_a b"[label="a b"];
+    "h_-1"[label="-1"];
+    "g_a b" -> "g_-1"[label=""];
+    "h_a b" -> "h_-1"[label=""];
+}
+"#);
+    }
+
+    #[test]
+    fn union_prefixes_subgraphs_groups_and_ports() {
+        let half = |label| {
+            let labels: Trivial = UnlabelledNodes(2);
+            let mut g = LabelledGraph::new("half", labels,
+                                           vec![Edge { samehead: Some("in"), sametail: Some("out"),
+                                                       ..edge(0, 1, "", Style::None, None) }],
+                                           None);
+            g.node_extras[0].subgraph = Some("cluster_x");
+            g.node_extras[0].group = Some("top");
+            g.subgraph_attrs = vec![("cluster_x", "label", label)];
+            g
+        };
+        let (first, second) = (half("First"), half("Second"));
+        let both = union(&first, &second, "a_", "b_");
+        assert_eq!(render_to_string(&both).unwrap(),
+r#"digraph half {
+    subgraph clustera__x {
+        label="First";
+        a_N0[label="N0"][group=a_top];
+    }
+    subgraph clusterb__x {
+        label="Second";
+        b_N0[label="N0"][group=b_top];
+    }
+    a_N1[label="N1"];
+    b_N1[label="N1"];
+    a_N0 -> a_N1[label=""][samehead=a_in][sametail=a_out];
+    b_N0 -> b_N1[label=""][samehead=b_in][sametail=b_out];
+}
+"#);
+    }
+
+    #[test]
+    fn computed_attributes() {
+        let r = test_input_default(
+            DefaultStyleGraph::new("g", 2, vec![], Kind::Digraph)
+                .with_computed_attrs(true));
+        assert_eq!(
+            r.unwrap(),
+            r#"digraph g {
+    comment="2 nodes";
+    N0[label="N0"][tooltip="node 0"];
+    N1[label="N1"][tooltip="node 1"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn render_to_string_with_size_hints() {
+        let edges = vec![(0, 1), (0, 2), (1, 3), (2, 3)];
+        let plain = DefaultStyleGraph::new("g", 4, edges.clone(), Kind::Digraph);
+        let hinted = DefaultStyleGraph::new("g", 4, edges, Kind::Digraph).with_size_hints(true);
+        assert_eq!(hinted.num_nodes(), Some(4));
+        assert_eq!(hinted.num_edges(), Some(4));
+
+        let expected = test_input_default(
+            DefaultStyleGraph::new("g", 4, vec![(0, 1), (0, 2), (1, 3), (2, 3)], Kind::Digraph)).unwrap();
+        assert_eq!(render_to_string(&plain).unwrap(), expected);
+        assert_eq!(render_to_string(&hinted).unwrap(), expected);
+    }
+
+    #[test]
+    fn render_several_graphs() {
+        let graphs = vec![DefaultStyleGraph::new("first", 2, vec![(0, 1)], Kind::Digraph),
+                          DefaultStyleGraph::new("second", 2, vec![(1, 0)], Kind::Graph)];
+        let mut writer = Vec::new();
+        render_all(&graphs, &mut writer, &[]).unwrap();
+        let r = String::from_utf8(writer).unwrap();
+        assert_eq!(r,
+r#"digraph first {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label=""];
+}
+
+graph second {
     N0[label="N0"];
-    N0 -> N0[label="E"];
+    N1[label="N1"];
+    N1 -- N0[label=""];
 }
 "#);
+        assert_eq!(validate(&r), Ok(()));
     }
 
     #[test]
-    fn hasse_diagram() {
-        let labels = AllNodesLabelled(vec!("{x,y}", "{x}", "{y}", "{}"));
-        let r = test_input(LabelledGraph::new("hasse_diagram",
-                                              labels,
-                                              vec![edge(0, 1, "", Style::None, Some("green")),
-                                                   edge(0, 2, "", Style::None, Some("blue")),
-                                                   edge(1, 3, "", Style::None, Some("red")),
-                                                   edge(2, 3, "", Style::None, Some("black"))],
-                                              None));
-        assert_eq!(r.unwrap(),
-r#"digraph hasse_diagram {
-    N0[label="{x,y}"];
-    N1[label="{x}"];
-    N2[label="{y}"];
-    N3[label="{}"];
-    N0 -> N1[label=""][color="green"];
-    N0 -> N2[label=""][color="blue"];
-    N1 -> N3[label=""][color="red"];
-    N2 -> N3[label=""][color="black"];
-}
-"#);
+    fn validate_rendered_output() {
+        let g = DefaultStyleGraph::new("g", 3, vec![(0, 1), (1, 2)], Kind::Graph);
+        assert_eq!(validate(&render_to_string(&g).unwrap()), Ok(()));
+        let g = DefaultStyleGraph::new("g", 2, vec![(0, 1)], Kind::Digraph).with_computed_attrs(true);
+        assert_eq!(validate(&render_to_string(&g).unwrap()), Ok(()));
+        let labels: Trivial = UnlabelledNodes(2);
+        let mut g = LabelledGraph::new("checked", labels,
+                                       vec![edge(0, 1, "say \"hi\" {x}", Style::Dashed, Some("red"))],
+                                       None);
+        g.node_extras[0].comment = Some("first // node");
+        g.node_extras[1].html_shape = Some("none");
+        assert_eq!(validate(&test_input(g).unwrap()), Ok(()));
+        assert_eq!(validate("strict digraph \"a b\" { a -> b [label=<<B>x</B>>]; }\n/* end */"), Ok(()));
+        assert_eq!(validate("digraph g {\n    a [label=\"x\",\n       color=red];\n}\n"), Ok(()));
+        assert_eq!(validate("digraph g {\n    a [label=\"two\nlines\"];\n}\n"), Ok(()));
+        assert_eq!(validate("digraph g {\n    subgraph cluster_a {\n        a;\n    }\n    {rank=same; a b}\n}\n"), Ok(()));
     }
 
     #[test]
-    fn left_aligned_text() {
-        let labels = AllNodesLabelled(vec!(
-            "if test {\
-           \\l    branch1\
-           \\l} else {\
-           \\l    branch2\
-           \\l}\
-           \\lafterward\
-           \\l",
-            "branch1",
-            "branch2",
-            "afterward"));
+    fn validate_broken_output() {
+        let err = validate("digraph g {\n    N0[label=\"N0\"];\n").unwrap_err();
+        assert_eq!((err.line(), err.column(), err.message()), (1, 11, "unclosed `{`"));
+        assert_eq!(err.to_string(), "1:11: unclosed `{`");
 
-        let mut writer = Vec::new();
+        let err = validate("digraph g {\n    N0[label=\"say \"hi\"\"];\n}\n").unwrap_err();
+        assert_eq!((err.line(), err.column(), err.message()), (2, 19, "unescaped quote in string"));
 
-        let g = LabelledGraphWithEscStrs::new("syntax_tree",
-                                              labels,
-                                              vec![edge(0, 1, "then", Style::None, None),
-                                                   edge(0, 2, "else", Style::None, None),
-                                                   edge(1, 3, ";", Style::None, None),
-                                                   edge(2, 3, ";", Style::None, None)]);
+        let err = validate("digraph g {\n    N0[label=\"N0\";\n}\n").unwrap_err();
+        assert_eq!((err.line(), err.column(), err.message()), (2, 7, "unterminated attribute list"));
 
-        render(&g, &mut writer).unwrap();
-        let mut r = String::new();
-        Read::read_to_string(&mut &*writer, &mut r).unwrap();
+        let err = validate("digraph g { a b c }").unwrap_err();
+        assert_eq!((err.line(), err.column(), err.message()), (1, 13, "unterminated statement"));
 
-        assert_eq!(r,
-r#"digraph syntax_tree {
-    N0[label="if test {\l    branch1\l} else {\l    branch2\l}\lafterward\l"];
-    N1[label="branch1"];
-    N2[label="branch2"];
-    N3[label="afterward"];
-    N0 -> N1[label="then"];
-    N0 -> N2[label="else"];
-    N1 -> N3[label=";"];
-    N2 -> N3[label=";"];
-}
-"#);
+        let err = validate("digraph g {\n    a [label=\"x\n}\n").unwrap_err();
+        assert_eq!((err.line(), err.column(), err.message()), (2, 14, "unterminated string"));
+
+        let err = validate("digraph g {\n}\n}\n").unwrap_err();
+        assert_eq!((err.line(), err.column(), err.message()), (3, 1, "unmatched `}`"));
+
+        assert_eq!(validate("graf g {}").unwrap_err().message(), "expected `graph` or `digraph`");
+        assert_eq!(validate("").unwrap_err().message(), "expected `graph` or `digraph`");
     }
 
     #[test]
-    fn simple_id_construction() {
-        let id1 = Id::new("hello");
-        match id1 {
-            Ok(_) => {}
-            Err(..) => panic!("'hello' is not a valid value for id anymore"),
-        }
+    fn render_to_file_matches_render() {
+        let g = DefaultStyleGraph::new("g", 3, vec![(0, 1), (1, 2)], Kind::Digraph);
+        let path = std::env::temp_dir().join(format!("dot-render-{}.dot", std::process::id()));
+        render_to_file(&g, &path).unwrap();
+        let written = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.unwrap(), render_to_string(&g).unwrap());
     }
 
     #[test]
-    fn test_some_arrow() {
-        let labels: Trivial = SomeNodesLabelled(vec![Some("A"), None]);
-        let styles = Some(vec![Style::None, Style::Dotted]);
-        let start  = Arrow::default();
-        let end    = Arrow::from_arrow(ArrowShape::crow());
-        let result = test_input(LabelledGraph::new("test_some_labelled",
-                                                   labels,
-                                                   vec![edge_with_arrows(0, 1, "A-1", Style::None, start, end, None)],
-                                                   styles));
-        assert_eq!(result.unwrap(),
-r#"digraph test_some_labelled {
-    N0[label="A"];
-    N1[label="N1"][style="dotted"];
-    N0 -> N1[label="A-1"][arrowhead="crow"];
-}
-"#);
+    fn render_lines_match_render() {
+        let g = DefaultStyleGraph::new("g", 3, vec![(0, 1), (1, 2)], Kind::Graph);
+        let lines = render_lines(&g).unwrap().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "graph g {");
+        assert_eq!(lines.iter().map(|l| format!("{}\n", l)).collect::<String>(),
+                   render_to_string(&g).unwrap());
     }
 
     #[test]
-    fn test_some_arrows() {
-        let labels: Trivial = SomeNodesLabelled(vec![Some("A"), None]);
-        let styles = Some(vec![Style::None, Style::Dotted]);
-        let start  = Arrow::from_arrow(ArrowShape::tee());
-        let end    = Arrow::from_arrow(ArrowShape::Crow(Side::Left));
-        let result = test_input(LabelledGraph::new("test_some_labelled",
-                                                   labels,
-                                                   vec![edge_with_arrows(0, 1, "A-1", Style::None, start, end, None)],
-                                                   styles));
-        assert_eq!(result.unwrap(),
-r#"digraph test_some_labelled {
-    N0[label="A"];
-    N1[label="N1"][style="dotted"];
-    N0 -> N1[label="A-1"][arrowhead="lcrow" dir="both" arrowtail="tee"];
-}
-"#);
+    fn render_lines_reports_errors() {
+        let labels: Trivial = UnlabelledNodes(1);
+        let mut g = LabelledGraph::new("lines", labels, vec![], None);
+        g.trailing = vec!["}"];
+        let err = render_lines(&g).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn badly_formatted_id() {
-        let id2 = Id::new("Weird { struct : ure } !!!");
-        match id2 {
-            Ok(_) => panic!("graphviz id suddenly allows spaces, brackets and stuff"),
-            Err(..) => {}
+    fn serde_round_trip() {
+        fn round_trip<T>(value: T)
+            where T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug
+        {
+            let json = serde_json::to_string(&value).unwrap();
+            assert_eq!(serde_json::from_str::<T>(&json).unwrap(), value);
         }
-    }
 
-    type SimpleEdge = (Node, Node);
-
-    struct DefaultStyleGraph {
-        /// The name for this graph. Used for labelling generated graph
-        name: &'static str,
-        nodes: usize,
-        edges: Vec<SimpleEdge>,
-        kind: Kind,
-        rankdir: Option<RankDir>,
+        round_trip(Style::Dashed);
+        round_trip(RankDir::LeftRight);
+        round_trip(ArrowShape::Crow(Side::Left));
+        round_trip(ArrowShape::Dot(Fill::Open, Side::Both));
+        round_trip(Splines::Ortho);
+        round_trip(Ordering::Out);
+        round_trip(ImagePos::TopLeft);
+        round_trip(NodeSize { width: Some(1.5), height: None, fixed: true });
+        round_trip(LabelLoc::Top);
+        round_trip(LabelJust::Left);
+        round_trip(Pack::Margin(8));
+        round_trip(PackMode::Cluster);
+        round_trip(Ratio::Numeric(0.5));
+        round_trip(Dir::Back);
+        round_trip(Shape::Mrecord);
+        round_trip(Sep::Additive(4.0));
+        round_trip(Overlap::Prism);
+        assert_eq!(serde_json::to_string(&Style::Dashed).unwrap(), r#""Dashed""#);
     }
 
-    impl DefaultStyleGraph {
-        fn new(name: &'static str,
-               nodes: usize,
-               edges: Vec<SimpleEdge>,
-               kind: Kind)
-               -> DefaultStyleGraph {
-            assert!(!name.is_empty());
-            DefaultStyleGraph {
-                name: name,
-                nodes: nodes,
-                edges: edges,
-                kind: kind,
-                rankdir: None,
-            }
-        }
-
-        fn with_rankdir(self, rankdir: Option<RankDir>) -> Self {
-            Self {
-                rankdir,
-                ..self
-            }
-        }
+    struct ShuffledGraph {
+        nodes: Vec<Node>,
+        edges: Vec<SimpleEdge>,
     }
 
-    impl<'a> Labeller<'a, Node, &'a SimpleEdge> for DefaultStyleGraph {
+    impl<'a> Labeller<'a, Node, &'a SimpleEdge> for ShuffledGraph {
         fn graph_id(&'a self) -> Id<'a> {
-            Id::new(&self.name[..]).unwrap()
+            Id::new("shuffled").unwrap()
         }
         fn node_id(&'a self, n: &Node) -> Id<'a> {
             id_name(n)
         }
-        fn kind(&self) -> Kind {
-            self.kind
-        }
-        fn rank_dir(&self) -> Option<RankDir> {
-            self.rankdir
-        }
     }
 
-    impl<'a> GraphWalk<'a, Node, &'a SimpleEdge> for DefaultStyleGraph {
+    impl<'a> GraphWalk<'a, Node, &'a SimpleEdge> for ShuffledGraph {
         fn nodes(&'a self) -> Nodes<'a, Node> {
-            (0..self.nodes).collect()
+            Nodes::Borrowed(&self.nodes)
         }
         fn edges(&'a self) -> Edges<'a, &'a SimpleEdge> {
             self.edges.iter().collect()
@@ -1637,42 +6414,119 @@ r#"digraph test_some_labelled {
         }
     }
 
-    fn test_input_default(g: DefaultStyleGraph) -> io::Result<String> {
+    #[test]
+    fn merged_attributes() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let mut g = LabelledGraph::new("merged", labels,
+                                       vec![edge(0, 1, "a, b", Style::Dotted, Some("red"))],
+                                       None);
+        g.node_extras[0].group = Some("top");
+        g.node_extras[0].fontcolor = Some("blue");
         let mut writer = Vec::new();
-        render(&g, &mut writer).unwrap();
-        let mut s = String::new();
-        Read::read_to_string(&mut &*writer, &mut s)?;
-        Ok(s)
+        render_opts(&g, &mut writer, &[RenderOption::MergeAttributes]).unwrap();
+        assert_eq!(String::from_utf8(writer).unwrap(),
+r#"digraph merged {
+    N0[label="N0", fontcolor="blue", group=top];
+    N1[label="N1"];
+    N0 -> N1[label="a, b", style="dotted", color="red"];
+}
+"#);
+
+        let mut writer = Vec::new();
+        render_opts(&g, &mut writer, &[RenderOption::MergeAttributes, RenderOption::Canonical]).unwrap();
+        assert!(String::from_utf8(writer).unwrap()
+                .contains("N0[fontcolor=\"blue\", group=top, label=\"N0\"];"));
     }
 
     #[test]
-    fn default_style_graph() {
-        let r = test_input_default(
-            DefaultStyleGraph::new("g", 4,
-                                   vec![(0, 1), (0, 2), (1, 3), (2, 3)],
-                                   Kind::Graph));
-        assert_eq!(r.unwrap(),
-r#"graph g {
+    fn canonical_statement_keeps_quoted_ids() {
+        assert_eq!(super::rewrite_statement(r#""x[1]"[shape="box"][label="x[1]"]"#, &[RenderOption::Canonical]),
+                   r#""x[1]"[label="x[1]"][shape="box"]"#);
+        assert_eq!(super::rewrite_statement(r#""a[" -> "b]"[label="e"]"#, &[RenderOption::Canonical]),
+                   r#""a[" -> "b]"[label="e"]"#);
+    }
+
+    #[test]
+    fn canonical_statement_keeps_unquoted_values() {
+        assert_eq!(super::rewrite_statement("N0[pos=1,2][label=\"N0\"][xlabel=a b]", &[RenderOption::Canonical]),
+                   "N0[label=\"N0\"][pos=1,2][xlabel=a b]");
+        assert_eq!(super::rewrite_statement("N0[pos=1,2][label=\"N0\"]", &[RenderOption::MergeAttributes]),
+                   "N0[pos=1,2, label=\"N0\"]");
+        assert_eq!(super::rewrite_statement("N0 -> N1[arrowhead=\"dot\" dir=\"both\" arrowtail=\"tee\"][label=\"\"]",
+                                            &[RenderOption::Canonical]),
+                   "N0 -> N1[arrowhead=\"dot\"][arrowtail=\"tee\"][dir=\"both\"][label=\"\"]");
+    }
+
+    #[test]
+    fn sorted_parallel_edges_are_stable() {
+        let render_edges = |labels: [&'static str; 3], option| {
+            let nodes: Trivial = UnlabelledNodes(2);
+            let g = LabelledGraph::new("parallel", nodes,
+                                       labels.iter().map(|&l| edge(0, 1, l, Style::None, None)).collect(),
+                                       None);
+            render_opts_to_string(&g, &[option]).unwrap()
+        };
+        for &option in &[RenderOption::SortEdges, RenderOption::Canonical] {
+            let first = render_edges(["b", "c", "a"], option);
+            assert_eq!(first, render_edges(["c", "a", "b"], option));
+            assert_eq!(first, render_edges(["a", "b", "c"], option));
+        }
+        assert!(render_edges(["b", "c", "a"], RenderOption::SortEdges)
+                .ends_with("N0 -> N1[label=\"a\"];\n    N0 -> N1[label=\"b\"];\n    N0 -> N1[label=\"c\"];\n}\n"));
+    }
+
+    #[test]
+    fn canonical_render_is_stable() {
+        let options = [RenderOption::Canonical];
+        let first = ShuffledGraph {
+            nodes: vec![2, 0, 3, 1],
+            edges: vec![(2, 3), (0, 2), (1, 3), (0, 1)],
+        };
+        let second = ShuffledGraph {
+            nodes: vec![1, 3, 0, 2],
+            edges: vec![(1, 3), (0, 1), (2, 3), (0, 2)],
+        };
+        let mut first_output = Vec::new();
+        render_opts(&first, &mut first_output, &options).unwrap();
+        let mut second_output = Vec::new();
+        render_opts(&second, &mut second_output, &options).unwrap();
+        assert_eq!(first_output, second_output);
+
+        let labels: Trivial = UnlabelledNodes(2);
+        let g = LabelledGraph::new("canonical", labels,
+                                   vec![edge_with_arrows(0, 1, "a b", Style::Dashed,
+                                                         Arrow::from_arrow(ArrowShape::Dot(Fill::Filled, Side::Both)),
+                                                         Arrow::default(), None)],
+                                   None);
+        let mut writer = Vec::new();
+        render_opts(&g, &mut writer, &options).unwrap();
+        assert_eq!(String::from_utf8(writer).unwrap(),
+r#"digraph canonical {
     N0[label="N0"];
     N1[label="N1"];
-    N2[label="N2"];
-    N3[label="N3"];
-    N0 -- N1[label=""];
-    N0 -- N2[label=""];
-    N1 -- N3[label=""];
-    N2 -- N3[label=""];
+    N0 -> N1[arrowtail="dot"][dir="both"][label="a b"][style="dashed"];
 }
 "#);
     }
 
     #[test]
-    fn default_style_digraph() {
-        let r = test_input_default(
-            DefaultStyleGraph::new("di", 4,
-                                   vec![(0, 1), (0, 2), (1, 3), (2, 3)],
-                                   Kind::Digraph));
-        assert_eq!(r.unwrap(),
-r#"digraph di {
+    fn sorted_nodes_and_edges() {
+        let options = [RenderOption::SortNodes, RenderOption::SortEdges];
+        let first = ShuffledGraph {
+            nodes: vec![2, 0, 3, 1],
+            edges: vec![(2, 3), (0, 2), (1, 3), (0, 1)],
+        };
+        let second = ShuffledGraph {
+            nodes: vec![1, 3, 0, 2],
+            edges: vec![(1, 3), (0, 1), (2, 3), (0, 2)],
+        };
+        let mut first_output = Vec::new();
+        render_opts(&first, &mut first_output, &options).unwrap();
+        let mut second_output = Vec::new();
+        render_opts(&second, &mut second_output, &options).unwrap();
+        assert_eq!(first_output, second_output);
+        assert_eq!(String::from_utf8(first_output).unwrap(),
+r#"digraph shuffled {
     N0[label="N0"];
     N1[label="N1"];
     N2[label="N2"];
@@ -1686,23 +6540,136 @@ r#"digraph di {
     }
 
     #[test]
-    fn digraph_with_rankdir() {
-        let r = test_input_default(
-            DefaultStyleGraph::new("di", 4, vec![(0, 1), (0, 2)],
-                                   Kind::Digraph)
-                .with_rankdir(Some(RankDir::LeftRight)));
-        assert_eq!(
-            r.unwrap(),
-            r#"digraph di {
-    rankdir="LR";
+    fn subgraph_fragment() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let g = LabelledGraph::new("fragment", labels, vec![edge(0, 1, "E", Style::None, None)], None);
+        let mut writer = Vec::new();
+        render_subgraph(&g, &mut writer, Kind::Graph, &[]).unwrap();
+        assert_eq!(String::from_utf8(writer).unwrap(),
+r#"subgraph fragment {
     N0[label="N0"];
     N1[label="N1"];
-    N2[label="N2"];
-    N3[label="N3"];
-    N0 -> N1[label=""];
-    N0 -> N2[label=""];
+    N0 -- N1[label="E"];
 }
-"#
-        );
+"#);
+    }
+
+    #[test]
+    fn edge_dirs() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let tee = Arrow::from_arrow(ArrowShape::tee());
+        let result = test_input(LabelledGraph::new("edge_dirs",
+                                                   labels,
+                                                   vec![Edge { dir: Some(Dir::Forward), ..edge(0, 1, "", Style::None, None) },
+                                                        Edge { dir: Some(Dir::Back), ..edge(0, 1, "", Style::None, None) },
+                                                        Edge { dir: Some(Dir::Both), ..edge(0, 1, "", Style::None, None) },
+                                                        Edge { dir: Some(Dir::None_), ..edge(0, 1, "", Style::None, None) },
+                                                        Edge { dir: Some(Dir::Back),
+                                                               ..edge_with_arrows(0, 1, "", Style::None,
+                                                                                  tee, Arrow::default(), None) }],
+                                                   None));
+        assert_eq!(result.unwrap(),
+r#"digraph edge_dirs {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label=""][dir=forward];
+    N0 -> N1[label=""][dir=back];
+    N0 -> N1[label=""][dir=both];
+    N0 -> N1[label=""][dir=none];
+    N0 -> N1[label=""][dir="back" arrowtail="tee"];
+}
+"#);
+    }
+
+    #[test]
+    fn render_hook() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let g = LabelledGraph::new("render_hook", labels, vec![edge(0, 1, "E", Style::None, None)], None);
+        let mut writer = Vec::new();
+        render_with_hook(&g, &mut writer, &[], |kind, line| {
+            if kind == StatementKind::Node {
+                line.push_str("[class=\"node\"]");
+            }
+        }).unwrap();
+        assert_eq!(String::from_utf8(writer).unwrap(),
+r#"digraph render_hook {
+    N0[label="N0"][class="node"];
+    N1[label="N1"][class="node"];
+    N0 -> N1[label="E"];
+}
+"#);
+    }
+
+    #[test]
+    fn render_in_parts() {
+        let labels: Trivial = SomeNodesLabelled(vec![Some("A"), None, Some("C")]);
+        let g = LabelledGraph::new("parts",
+                                   labels,
+                                   vec![edge(0, 1, "E1", Style::Dotted, Some("red")),
+                                        edge(1, 2, "E2", Style::None, None)],
+                                   Some(vec![Style::Bold, Style::None, Style::None]));
+        let options = [RenderOption::NoEdgeLabels];
+        let mut whole = Vec::new();
+        render_opts(&g, &mut whole, &options).unwrap();
+
+        let mut parts = Vec::new();
+        render_header(&g, &mut parts).unwrap();
+        render_nodes(&g, &mut parts, &options).unwrap();
+        render_edges(&g, &mut parts, &options).unwrap();
+        render_footer(&g, &mut parts).unwrap();
+        assert_eq!(String::from_utf8(parts).unwrap(), String::from_utf8(whole).unwrap());
+    }
+
+    #[test]
+    fn render_through_references() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let g = LabelledGraph::new("refs", labels, vec![edge(0, 1, "E", Style::None, None)], None);
+        let by_ref = &g;
+        let mut direct = Vec::new();
+        render(&g, &mut direct).unwrap();
+        let mut through = Vec::new();
+        render(&&by_ref, &mut through).unwrap();
+        assert_eq!(through, direct);
+    }
+
+    #[test]
+    fn merge_parallel_edges() {
+        let labels: Trivial = UnlabelledNodes(2);
+        let g = LabelledGraph::new("merged", labels,
+                                   vec![edge(0, 1, "first", Style::None, None),
+                                        edge(0, 1, "second", Style::Dashed, None),
+                                        edge(1, 0, "back", Style::None, None),
+                                        edge(0, 1, "third", Style::None, Some("red"))],
+                                   None);
+        let mut writer = Vec::new();
+        render_opts(&g, &mut writer, &[RenderOption::MergeParallelEdges]).unwrap();
+        assert_eq!(String::from_utf8(writer).unwrap(),
+r#"digraph merged {
+    N0[label="N0"];
+    N1[label="N1"];
+    N0 -> N1[label="first"];
+    N1 -> N0[label="back"];
+}
+"#);
+    }
+
+    #[test]
+    fn fontcolors_with_escaped_labels() {
+        let labels: Trivial = SomeNodesLabelled(vec![Some("left\\l"), None]);
+        let mut graph = LabelledGraph::new("fontcolors", labels,
+                                           vec![Edge { fontcolor: Some("#00ff00"),
+                                                       ..edge(0, 1, "edge\\l", Style::None, None) }],
+                                           None);
+        graph.node_extras[0].fontcolor = Some("dark red");
+        let g = LabelledGraphWithEscStrs { graph };
+        let mut writer = Vec::new();
+        render(&g, &mut writer).unwrap();
+        assert_eq!(String::from_utf8(writer).unwrap(),
+r##"digraph fontcolors {
+    N0[label="left\l"][fontcolor="dark red"];
+    N1[label="N1"];
+    N0 -> N1[label="edge\l"][fontcolor="#00ff00"];
+}
+"##);
     }
 }